@@ -0,0 +1,178 @@
+// Helpers shared by the upload scripts, evaluated in front of them
+
+/**
+* @typedef {{
+  max_dimension: number,
+  quality: number
+}} ImageOptions
+*/
+
+/**
+* square in source pixels
+* @typedef {{
+  x: number,
+  y: number,
+  size: number
+}} Crop
+*/
+
+/**
+* @typedef {{
+  backend_url: string,
+  token_key: string
+}} UploadTarget
+*/
+
+// gifs would lose their animation and svgs their scalability
+const REENCODED_TYPES = ["image/jpeg", "image/png", "image/webp"];
+// smaller images are only re-encoded when they have to be resized or cropped
+const REENCODE_SIZE = 512 * 1024;
+
+const EXTENSIONS = { "image/jpeg": "jpg", "image/png": "png", "image/webp": "webp" };
+
+/**
+ * @param {HTMLCanvasElement} canvas
+ * @param {string} type
+ * @param {number} quality
+ * @returns {Promise<Blob>}
+ */
+const toBlob = (canvas, type, quality) =>
+  new Promise((resolve, reject) =>
+    canvas.toBlob(
+      (blob) => (blob ? resolve(blob) : reject(new Error("failed to encode image"))),
+      type,
+      quality,
+    ),
+  );
+
+/**
+ * Downscales `file` so no side exceeds `options.max_dimension` and
+ * re-encodes it, with `crop` only that square is kept (the centered square
+ * when `square` is set). Anything that can't or needn't be processed is
+ * returned as is
+ *
+ * @param {File} file
+ * @param {ImageOptions} options
+ * @param {{ square?: boolean, crop?: Crop }} [shape]
+ * @returns {Promise<File>}
+ */
+async function prepareImage(file, options, shape = {}) {
+  // cropping still needs a new image, even if it is only the first frame
+  if (!REENCODED_TYPES.includes(file.type) && !shape.crop && !shape.square) {
+    return file;
+  }
+
+  /** @type {ImageBitmap} */
+  let bitmap;
+
+  try {
+    bitmap = await createImageBitmap(file);
+  } catch (e) {
+    console.warn("failed to decode image", e);
+
+    return file;
+  }
+
+  let source = { x: 0, y: 0, width: bitmap.width, height: bitmap.height };
+
+  if (shape.crop) {
+    const { x, y, size } = shape.crop;
+    source = { x, y, width: size, height: size };
+  } else if (shape.square) {
+    const size = Math.min(bitmap.width, bitmap.height);
+    source = {
+      x: (bitmap.width - size) / 2,
+      y: (bitmap.height - size) / 2,
+      width: size,
+      height: size,
+    };
+  }
+
+  const cropped = source.width !== bitmap.width || source.height !== bitmap.height;
+  const scale = Math.min(1, options.max_dimension / Math.max(source.width, source.height));
+
+  if (!cropped && scale === 1 && file.size <= REENCODE_SIZE) {
+    bitmap.close();
+
+    return file;
+  }
+
+  const canvas = document.createElement("canvas");
+  canvas.width = Math.max(1, Math.round(source.width * scale));
+  canvas.height = Math.max(1, Math.round(source.height * scale));
+
+  const context = canvas.getContext("2d");
+  context.imageSmoothingQuality = "high";
+  context.drawImage(
+    bitmap,
+    source.x,
+    source.y,
+    source.width,
+    source.height,
+    0,
+    0,
+    canvas.width,
+    canvas.height,
+  );
+  bitmap.close();
+
+  // photos stay jpeg, screenshots and transparent images go to webp which
+  // browsers without an encoder for it turn into png
+  const type = file.type === "image/jpeg" ? "image/jpeg" : "image/webp";
+  const blob = await toBlob(canvas, type, options.quality);
+
+  if (!cropped && scale === 1 && blob.size >= file.size) {
+    return file;
+  }
+
+  const base = file.name.replace(/\.[^.]*$/, "") || "image";
+
+  return new File([blob], `${base}.${EXTENSIONS[blob.type] ?? "png"}`, { type: blob.type });
+}
+
+/**
+ * Posts `file` to the media endpoint, resolves with the parsed response
+ *
+ * @param {UploadTarget} target
+ * @param {File} file
+ * @param {(loaded: number, total: number) => void} [onProgress]
+ * @returns {Promise<{ path: string }>}
+ */
+function uploadFile(target, file, onProgress) {
+  return new Promise((resolve, reject) => {
+    const xhr = new XMLHttpRequest();
+    xhr.open("POST", `${target.backend_url}/media/upload`);
+
+    const token = localStorage.getItem(target.token_key);
+    if (token) {
+      xhr.setRequestHeader("Authorization", `Bearer ${token}`);
+    }
+
+    xhr.upload.onprogress = (e) => {
+      if (e.lengthComputable && onProgress) {
+        onProgress(e.loaded, e.total);
+      }
+    };
+
+    xhr.onload = () => {
+      if (xhr.status < 200 || xhr.status >= 300) {
+        reject(new Error(`upload failed (${xhr.status})`));
+
+        return;
+      }
+
+      try {
+        resolve(JSON.parse(xhr.responseText));
+      } catch (e) {
+        reject(new Error("invalid upload response"));
+      }
+    };
+
+    xhr.onerror = () => reject(new Error("network error"));
+
+    const form = new FormData();
+    form.append("file", file, file.name);
+
+    xhr.send(form);
+  });
+}
@@ -0,0 +1,48 @@
+// Shows one message notification, answers true when it is clicked and false
+// once it goes away or wasn't shown at all
+
+/**
+* @type {{
+  tag: string,
+  title: string,
+  body: string,
+  icon: string | null,
+  selected: boolean
+}}
+*/
+const options = await dioxus.recv();
+
+// notifications of a chat replace each other, the replaced one is done
+const shown = (window.wppNotifications ??= {});
+
+/** @param {boolean} clicked */
+const done = (clicked) => {
+  if (shown[options.tag] === done) {
+    delete shown[options.tag];
+  }
+
+  dioxus.send(clicked);
+};
+
+const visible = document.visibilityState === "visible";
+
+if (!("Notification" in window) || Notification.permission !== "granted" || (options.selected && visible)) {
+  dioxus.send(false);
+} else {
+  shown[options.tag]?.(false);
+  shown[options.tag] = done;
+
+  const notification = new Notification(options.title, {
+    body: options.body,
+    tag: options.tag,
+    icon: options.icon ?? undefined,
+  });
+
+  notification.onclick = () => {
+    window.focus();
+    notification.close();
+    done(true);
+  };
+  notification.onclose = () => done(false);
+  notification.onerror = () => done(false);
+}
@@ -0,0 +1,51 @@
+// Reports "Online" or "Away" whenever it changes, away means the page is
+// hidden or there was no input for a while
+
+/** @type {number} */
+const idleAfter = await dioxus.recv();
+
+/** @type {"Online" | "Away" | null} */
+let status = null;
+let timer = undefined;
+let lastActivity = 0;
+
+/** @param {"Online" | "Away"} next */
+const report = (next) => {
+  if (next !== status) {
+    status = next;
+    dioxus.send(next);
+  }
+};
+
+const activity = () => {
+  clearTimeout(timer);
+
+  if (document.visibilityState === "hidden") {
+    report("Away");
+
+    return;
+  }
+
+  lastActivity = Date.now();
+  report("Online");
+
+  timer = setTimeout(() => report("Away"), idleAfter);
+};
+
+// pointer moves come in bursts, only restart the timer once a second
+const throttled = () => {
+  if (status !== "Online" || Date.now() - lastActivity > 1000) {
+    activity();
+  }
+};
+
+for (const event of ["pointermove", "pointerdown", "keydown", "wheel", "touchstart", "focus"]) {
+  window.addEventListener(event, throttled, { passive: true });
+}
+
+document.addEventListener("visibilitychange", activity);
+
+activity();
+
+// keep the channel open
+await new Promise(() => {});
@@ -0,0 +1,160 @@
+/**
+* @typedef {{
+  backend_url: string,
+  token_key: string,
+  max_dimension: number,
+  quality: number
+}} UploadConfig
+*/
+
+/**
+* @typedef {{ t: "Started", id: number, chat_id: string, name: string, mime: string, size: number }
+  | { t: "Progress", id: number, loaded: number, total: number }
+  | { t: "Done", id: number, chat_id: string, path: string, name: string, mime: string }
+  | { t: "Failed", id: number, error: string }} UploadEvent
+*/
+
+// listeners are installed once, later runs only take over reporting,
+// `prepareImage` and `uploadFile` come from media.js
+const state = (window.wppUploads ??= { next: 0, installed: false });
+
+/** @param {UploadEvent} event */
+state.send = (event) => {
+  try {
+    dioxus.send(event);
+  } catch (e) {
+    console.warn("upload event dropped", e);
+  }
+};
+
+/** @param {File} file */
+const mime = (file) => file.type || "application/octet-stream";
+
+/**
+* The chat is taken from the form the file came in through, before
+* compression so switching chats meanwhile doesn't move the file
+* @param {EventTarget | null} target
+* @returns {(original: File) => void}
+*/
+const uploadFrom = (target) => {
+  const form = target instanceof Element ? target.closest("#message-form") : null;
+  const chatId = form instanceof HTMLElement ? form.dataset.chatId : undefined;
+
+  return (original) => {
+    if (chatId) {
+      upload(chatId, original);
+    }
+  };
+};
+
+/**
+* @param {string} chatId
+* @param {File} original
+*/
+const upload = async (chatId, original) => {
+  const id = state.next++;
+
+  state.send({
+    t: "Started",
+    id,
+    chat_id: chatId,
+    name: original.name,
+    mime: mime(original),
+    size: original.size,
+  });
+
+  let file = original;
+
+  try {
+    file = await prepareImage(original, state.config);
+  } catch (e) {
+    console.warn("failed to compress image", e);
+  }
+
+  try {
+    const { path } = await uploadFile(state.config, file, (loaded, total) =>
+      state.send({ t: "Progress", id, loaded, total }),
+    );
+
+    state.send({
+      t: "Done",
+      id,
+      chat_id: chatId,
+      path,
+      name: file.name,
+      mime: mime(file),
+    });
+  } catch (e) {
+    state.send({ t: "Failed", id, error: e.message });
+  }
+};
+
+/** @param {DragEvent} e */
+const dropTarget = (e) =>
+  e.target instanceof Element &&
+  e.dataTransfer?.types.includes("Files") &&
+  e.target.closest("#message-form");
+
+const highlight = ["ring-2", "ring-blue-400"];
+
+if (!state.installed) {
+  state.installed = true;
+
+  document.addEventListener("change", (e) => {
+    if (e.target instanceof HTMLInputElement && e.target.id === "attachment-input") {
+      Array.from(e.target.files ?? []).forEach(uploadFrom(e.target));
+
+      // picking the same file again still fires change
+      e.target.value = "";
+    }
+  });
+
+  document.addEventListener("paste", (e) => {
+    if (!(e.target instanceof HTMLElement) || e.target.id !== "message") {
+      return;
+    }
+
+    const images = Array.from(e.clipboardData?.files ?? []).filter((x) =>
+      x.type.startsWith("image/"),
+    );
+
+    // text pastes go through untouched
+    if (images.length > 0) {
+      e.preventDefault();
+      images.forEach(uploadFrom(e.target));
+    }
+  });
+
+  document.addEventListener("dragover", (e) => {
+    const form = dropTarget(e);
+
+    if (form) {
+      e.preventDefault();
+      form.classList.add(...highlight);
+    }
+  });
+
+  document.addEventListener("dragleave", (e) => {
+    const form = dropTarget(e);
+
+    if (form && !form.contains(/** @type {Node | null} */ (e.relatedTarget))) {
+      form.classList.remove(...highlight);
+    }
+  });
+
+  document.addEventListener("drop", (e) => {
+    const form = dropTarget(e);
+
+    if (form) {
+      e.preventDefault();
+      form.classList.remove(...highlight);
+
+      Array.from(e.dataTransfer?.files ?? []).forEach(uploadFrom(form));
+    }
+  });
+}
+
+while (true) {
+  /** @type {UploadConfig} */
+  state.config = await dioxus.recv();
+}
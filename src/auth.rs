@@ -0,0 +1,314 @@
+use anyhow::{anyhow, Context};
+use chrono::Utc;
+use dioxus::prelude::*;
+use dioxus_logger::tracing::warn;
+use gloo_timers::future::TimeoutFuture;
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use shared::api::user::{AuthResponse, Claims};
+
+use crate::{
+    config::Config, storage, ws::outbox, CHATS, CLAIMS, FOCUS_CHAT, HYDRATED, MESSAGE_STATES,
+    NOTIFICATIONS, PRESENCE, REACTIONS, REPLIES, SELECTED_CHAT, TYPING, UPLOADS, USER,
+};
+
+#[derive(Clone)]
+pub struct Auth {
+    pub claims: Claims,
+    pub token: String,
+}
+
+pub(crate) const TOKEN_KEY: &str = "jwt_token";
+const JWKS_KEY: &str = "jwks";
+const JWKS_FETCHED_KEY: &str = "jwks_fetched_at";
+const JWKS_PATH: &str = "/.well-known/jwks.json";
+
+/// PEM encoded RS256 public key, when not compiled in the key set is fetched
+/// from `JWKS_PATH` and cached in local storage
+static PUBLIC_KEY: Option<&str> = option_env!("JWT_PUBLIC_KEY");
+
+/// The cached key set is refetched once it is this old
+const JWKS_MAX_AGE_SECS: i64 = 24 * 60 * 60;
+
+/// Refresh the token this long before it expires
+const REFRESH_MARGIN_SECS: i64 = 120;
+
+pub(crate) fn local_storage() -> anyhow::Result<web_sys::Storage> {
+    web_sys::window()
+        .context("failed to get window")?
+        .local_storage()
+        .map_err(|_| anyhow!("failed to get local storage"))?
+        .context("failed to get local storage")
+}
+
+fn cached_jwks() -> Option<JwkSet> {
+    let jwks = local_storage().ok()?.get_item(JWKS_KEY).ok()??;
+
+    serde_json::from_str(&jwks).ok()
+}
+
+fn jwks_expired() -> bool {
+    let fetched_at = local_storage()
+        .ok()
+        .and_then(|x| x.get_item(JWKS_FETCHED_KEY).ok()?)
+        .and_then(|x| x.parse::<i64>().ok());
+
+    fetched_at.is_none_or(|x| Utc::now().timestamp() - x > JWKS_MAX_AGE_SECS)
+}
+
+async fn fetch_jwks() -> anyhow::Result<()> {
+    let config = consume_context::<Config>();
+
+    let client = reqwest::Client::new();
+    let jwks = client
+        .get(format!("{}{}", config.backend_url, JWKS_PATH))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<JwkSet>()
+        .await?;
+
+    let storage = local_storage()?;
+
+    storage
+        .set_item(JWKS_KEY, serde_json::to_string(&jwks)?.as_str())
+        .and_then(|_| storage.set_item(JWKS_FETCHED_KEY, &Utc::now().timestamp().to_string()))
+        .map_err(|_| anyhow!("failed to update local storage"))?;
+
+    Ok(())
+}
+
+/// Fetches the server key set unless the key is compiled in or cached,
+/// `force` refetches in case the server rotated its keys. An expired key set
+/// keeps verifying tokens while it is refetched in the background, so a
+/// valid session survives being offline
+async fn load_public_key(force: bool) -> anyhow::Result<()> {
+    if PUBLIC_KEY.is_some() {
+        return Ok(());
+    }
+
+    if !force && cached_jwks().is_some() {
+        if jwks_expired() {
+            spawn(async {
+                if let Err(e) = fetch_jwks().await {
+                    warn!("failed to refresh public key {}", e);
+                }
+            });
+        }
+
+        return Ok(());
+    }
+
+    fetch_jwks().await
+}
+
+enum TokenKey {
+    /// no key set loaded yet or the token was signed with a key it doesn't
+    /// have, a fresh key set may know it
+    Missing,
+    Found(DecodingKey),
+}
+
+fn decoding_key(token: &str) -> anyhow::Result<TokenKey> {
+    if let Some(pem) = PUBLIC_KEY {
+        return Ok(TokenKey::Found(DecodingKey::from_rsa_pem(pem.as_bytes())?));
+    }
+
+    let Some(jwks) = cached_jwks() else {
+        return Ok(TokenKey::Missing);
+    };
+
+    let header = jsonwebtoken::decode_header(token)?;
+    let jwk = match header.kid {
+        Some(kid) => jwks.find(&kid),
+        None => jwks.keys.first(),
+    };
+
+    match jwk {
+        Some(jwk) => Ok(TokenKey::Found(DecodingKey::from_jwk(jwk)?)),
+        None => Ok(TokenKey::Missing),
+    }
+}
+
+fn stored_token() -> Option<String> {
+    local_storage().ok()?.get_item(TOKEN_KEY).ok()?
+}
+
+fn verify(token: &str, key: &DecodingKey) -> anyhow::Result<Claims> {
+    let validation = Validation::new(Algorithm::RS256);
+
+    Ok(jsonwebtoken::decode::<Claims>(token, key, &validation)?.claims)
+}
+
+/// Session stored by a previous visit, used to initialize `CLAIMS`
+pub fn restore() -> Option<Auth> {
+    restore_with(false)
+}
+
+/// `fresh_keys` when the key set was just fetched, a token it has no key for
+/// is dropped then, otherwise `init` retries after fetching
+fn restore_with(fresh_keys: bool) -> Option<Auth> {
+    let storage = local_storage().ok()?;
+    let token = storage.get_item(TOKEN_KEY).ok()??;
+
+    let claims = match decoding_key(&token) {
+        Ok(TokenKey::Found(key)) => verify(&token, &key),
+        Ok(TokenKey::Missing) if !fresh_keys => return None,
+        Ok(TokenKey::Missing) => Err(anyhow!("no matching public key")),
+        Err(e) => Err(e),
+    };
+
+    match claims {
+        Ok(claims) => Some(Auth { claims, token }),
+        Err(e) => {
+            warn!("dropping stored token {}", e);
+            storage.remove_item(TOKEN_KEY).ok()?;
+
+            None
+        }
+    }
+}
+
+/// Verifies `token` and starts a session with it
+pub async fn login(token: String) -> anyhow::Result<()> {
+    load_public_key(false).await?;
+
+    let claims = match decoding_key(&token)? {
+        TokenKey::Found(key) => verify(&token, &key),
+        // the server may have rotated its keys
+        TokenKey::Missing => {
+            load_public_key(true).await?;
+
+            match decoding_key(&token)? {
+                TokenKey::Found(key) => verify(&token, &key),
+                TokenKey::Missing => Err(anyhow!("no matching public key")),
+            }
+        }
+    }?;
+
+    local_storage()?
+        .set_item(TOKEN_KEY, token.as_str())
+        .map_err(|_| anyhow!("failed to update local storage"))?;
+
+    *CLAIMS.write() = Some(Auth { claims, token });
+
+    Ok(())
+}
+
+pub fn logout() {
+    // drop the cache while we still know whose it is
+    storage::clear();
+
+    if let Ok(storage) = local_storage() {
+        let _ = storage.remove_item(TOKEN_KEY);
+    }
+
+    *CLAIMS.write() = None;
+    *USER.write() = None;
+    *HYDRATED.write() = None;
+
+    // nothing of this session may show up or be sent for the next user
+    CHATS.write().clear();
+    outbox::clear();
+    MESSAGE_STATES.write().clear();
+    REPLIES.write().clear();
+    REACTIONS.write().clear();
+    TYPING.write().clear();
+    PRESENCE.write().clear();
+    UPLOADS.write().clear();
+    *SELECTED_CHAT.write() = None;
+    *FOCUS_CHAT.write() = None;
+    *NOTIFICATIONS.write() = Default::default();
+}
+
+async fn refresh(token: &str) -> anyhow::Result<()> {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        HeaderName::from_static("authorization"),
+        HeaderValue::from_str(format!("Bearer {}", token).as_str())?,
+    );
+
+    let config = consume_context::<Config>();
+
+    let client = reqwest::Client::new();
+    let res = client
+        .post(format!("{}/user/refresh", config.backend_url))
+        .headers(headers)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<AuthResponse>()
+        .await?;
+
+    login(res.token).await
+}
+
+/// Loads the public key and restores a stored session that couldn't be
+/// verified before the key was there
+pub async fn init() {
+    if let Err(e) = load_public_key(false).await {
+        warn!("failed to load public key {}", e);
+
+        return;
+    }
+
+    if CLAIMS.peek().is_none() {
+        // signed with a key we don't know yet, the server may have rotated
+        // its keys since they were cached
+        let unknown_key =
+            stored_token().is_some_and(|x| matches!(decoding_key(&x), Ok(TokenKey::Missing)));
+
+        if unknown_key {
+            if let Err(e) = load_public_key(true).await {
+                warn!("failed to refetch public key {}", e);
+
+                return;
+            }
+        }
+
+        let auth = restore_with(true);
+
+        if auth.is_some() {
+            *CLAIMS.write() = auth;
+        }
+    }
+}
+
+/// Refreshes the token when it is about to expire and logs out once it has
+pub async fn watch_expiry() {
+    loop {
+        let auth = CLAIMS.peek().clone();
+
+        let auth = match auth {
+            Some(auth) => auth,
+            None => {
+                TimeoutFuture::new(1000).await;
+
+                continue;
+            }
+        };
+
+        let remaining = auth.claims.exp as i64 - Utc::now().timestamp();
+
+        if remaining > REFRESH_MARGIN_SECS {
+            // wake up regularly in case the session changes
+            let wait = (remaining - REFRESH_MARGIN_SECS).min(60);
+            TimeoutFuture::new(wait as u32 * 1000).await;
+
+            continue;
+        }
+
+        if let Err(e) = refresh(&auth.token).await {
+            warn!("failed to refresh token {}", e);
+
+            if remaining <= 0 {
+                logout();
+
+                continue;
+            }
+
+            TimeoutFuture::new(10000).await;
+        }
+    }
+}
@@ -1,3 +1,13 @@
 pub mod avatar;
+pub mod avatar_editor;
+pub mod chat_settings;
+pub mod connection_banner;
+pub mod link_preview;
+pub mod markdown;
 pub mod navbar;
+pub mod reactions;
+pub mod receipts;
 pub mod sidebar;
+pub mod thread;
+pub mod typing;
+pub mod uploads;
@@ -1,10 +1,12 @@
 use dioxus::prelude::*;
 
+use crate::ws::ext::PresenceStatus;
+
 #[derive(Clone, PartialEq)]
 pub enum Size {
     Small,
     Medium,
-    _Large,
+    Large,
 }
 
 const COLORS: [&str; 8] = [
@@ -18,12 +20,51 @@ const COLORS: [&str; 8] = [
     "bg-gray-500",
 ];
 
+/// Dot in the corner of an avatar
 #[component]
-pub fn Avatar(src: Option<String>, alt: String, size: Size) -> Element {
+fn StatusDot(status: PresenceStatus) -> Element {
+    let (class, title) = match status {
+        PresenceStatus::Online => ("bg-green-500", "Online"),
+        PresenceStatus::Away => ("bg-yellow-400", "Away"),
+        PresenceStatus::Offline => ("bg-gray-400", "Offline"),
+    };
+
+    rsx! {
+        span {
+            class: "absolute bottom-0 right-0 w-2.5 h-2.5 rounded-full ring-2 ring-white {class}",
+            title
+        }
+    }
+}
+
+/// `status` adds a presence dot
+#[component]
+pub fn Avatar(
+    src: Option<String>,
+    alt: String,
+    size: Size,
+    status: Option<PresenceStatus>,
+) -> Element {
+    if let Some(status) = status {
+        return rsx! {
+            div {
+                class: "relative flex-shrink-0",
+                Avatar {
+                    src,
+                    alt,
+                    size
+                }
+                StatusDot {
+                    status
+                }
+            }
+        };
+    }
+
     let size_class = match size {
         Size::Small => "w-8 h-8 text-sm",
         Size::Medium => "w-10 h-10 text-base",
-        Size::_Large => "w-12 h-12 text-lg",
+        Size::Large => "w-12 h-12 text-lg",
     };
 
     if let Some(src) = src {
@@ -32,7 +73,7 @@ pub fn Avatar(src: Option<String>, alt: String, size: Size) -> Element {
                 img {
                     src,
                     alt,
-                    class: "{size_class} rounded-full flex items-center justify-center font-semibold text-white"
+                    class: "{size_class} rounded-full object-cover flex items-center justify-center font-semibold text-white"
                 }
             };
         }
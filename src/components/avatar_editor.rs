@@ -0,0 +1,189 @@
+use dioxus::prelude::*;
+
+use super::avatar::{Avatar, Size};
+use crate::upload::{AvatarSource, Crop};
+
+/// Side of the crop area in css pixels
+const VIEWPORT: f64 = 256.0;
+const MAX_ZOOM: f64 = 4.0;
+
+/// Image size, zoom and where the image sits in the crop area, at zoom 1
+/// its shorter side fills the area
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct View {
+    width: f64,
+    height: f64,
+    zoom: f64,
+    left: f64,
+    top: f64,
+}
+
+impl View {
+    fn centered(source: &AvatarSource) -> Self {
+        let view = Self {
+            width: source.width,
+            height: source.height,
+            zoom: 1.0,
+            left: 0.0,
+            top: 0.0,
+        };
+        let scale = view.scale();
+
+        Self {
+            left: (VIEWPORT - view.width * scale) / 2.0,
+            top: (VIEWPORT - view.height * scale) / 2.0,
+            ..view
+        }
+    }
+
+    fn scale(&self) -> f64 {
+        VIEWPORT / self.width.min(self.height) * self.zoom
+    }
+
+    /// Moves by `(x, y)` as far as the crop area stays covered by the image
+    fn pan(self, x: f64, y: f64) -> Self {
+        let scale = self.scale();
+
+        // at zoom 1 the shorter side can end up a rounding error short of
+        // the crop area, `clamp` panics if the bounds cross
+        Self {
+            left: (self.left + x).max(VIEWPORT - self.width * scale).min(0.0),
+            top: (self.top + y).max(VIEWPORT - self.height * scale).min(0.0),
+            ..self
+        }
+    }
+
+    /// Zooms around the middle of the crop area
+    fn zoom(self, zoom: f64) -> Self {
+        let zoomed = Self {
+            zoom: zoom.clamp(1.0, MAX_ZOOM),
+            ..self
+        };
+        let ratio = zoomed.scale() / self.scale();
+        let center = VIEWPORT / 2.0;
+
+        Self {
+            left: center - (center - self.left) * ratio,
+            top: center - (center - self.top) * ratio,
+            ..zoomed
+        }
+        .pan(0.0, 0.0)
+    }
+
+    fn crop(&self) -> Crop {
+        let scale = self.scale();
+
+        Crop {
+            x: -self.left / scale,
+            y: -self.top / scale,
+            size: VIEWPORT / scale,
+        }
+    }
+}
+
+/// Drag and zoom to pick the square of `source` used as the avatar,
+/// `preview` is the last crop as it will be uploaded
+#[component]
+pub fn AvatarEditor(
+    source: AvatarSource,
+    preview: Option<String>,
+    name: String,
+    oncrop: EventHandler<Crop>,
+) -> Element {
+    let mut view_signal = use_signal(|| View::centered(&source));
+    // last pointer position while dragging
+    let mut drag_signal = use_signal::<Option<(f64, f64)>>(|| None);
+
+    // the initial crop, later ones follow each drag or zoom
+    use_effect(move || oncrop.call(view_signal.peek().crop()));
+
+    let mut end_drag = move || {
+        if drag_signal.take().is_some() {
+            oncrop.call(view_signal.peek().crop());
+        }
+    };
+
+    let view = view_signal();
+    let scale = view.scale();
+    let width = view.width * scale;
+    let height = view.height * scale;
+
+    rsx! {
+        div {
+            class: "flex flex-wrap items-end gap-6",
+            div {
+                class: "relative overflow-hidden rounded bg-gray-900 cursor-move select-none touch-none",
+                style: "width: {VIEWPORT}px; height: {VIEWPORT}px",
+                onpointerdown: move |evt| {
+                    let point = evt.client_coordinates();
+
+                    drag_signal.set(Some((point.x, point.y)));
+                },
+                onpointermove: move |evt| {
+                    let Some((x, y)) = drag_signal() else {
+                        return;
+                    };
+
+                    let point = evt.client_coordinates();
+                    let view = *view_signal.peek();
+
+                    view_signal.set(view.pan(point.x - x, point.y - y));
+                    drag_signal.set(Some((point.x, point.y)));
+                },
+                onpointerup: move |_| end_drag(),
+                onpointerleave: move |_| end_drag(),
+                img {
+                    src: "{source.url}",
+                    alt: "",
+                    draggable: "false",
+                    class: "absolute max-w-none pointer-events-none",
+                    style: "left: {view.left}px; top: {view.top}px; width: {width}px; height: {height}px"
+                }
+                // darkens what falls outside the round avatar
+                div {
+                    class: "absolute inset-0 rounded-full pointer-events-none",
+                    style: "box-shadow: 0 0 0 {VIEWPORT}px rgba(0, 0, 0, 0.5)"
+                }
+            }
+            div {
+                class: "flex-1 min-w-48 space-y-4",
+                label {
+                    class: "block text-sm font-medium text-gray-700",
+                    "Zoom"
+                    input {
+                        r#type: "range",
+                        class: "block w-full mt-1",
+                        min: 1,
+                        max: MAX_ZOOM,
+                        step: 0.01,
+                        value: view.zoom,
+                        oninput: move |evt| {
+                            if let Ok(zoom) = evt.value().parse::<f64>() {
+                                let view = *view_signal.peek();
+
+                                view_signal.set(view.zoom(zoom));
+                            }
+                        },
+                        onchange: move |_| {
+                            oncrop.call(view_signal.peek().crop());
+                        }
+                    }
+                }
+                div {
+                    class: "flex items-end gap-4",
+                    for size in [Size::Small, Size::Medium, Size::Large] {
+                        Avatar {
+                            src: preview.clone(),
+                            alt: name.clone(),
+                            size
+                        }
+                    }
+                }
+                p {
+                    class: "text-xs text-gray-500",
+                    "Drag to move the image, the preview shows how it will look across the app"
+                }
+            }
+        }
+    }
+}
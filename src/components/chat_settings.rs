@@ -0,0 +1,178 @@
+use bson::oid::ObjectId;
+use chrono::{Local, NaiveTime, TimeDelta, Utc};
+use dioxus::prelude::*;
+
+use crate::{
+    notifications::{self, Mode, QuietHours},
+    NOTIFICATIONS,
+};
+
+const MUTE_FOR: [(&str, TimeDelta); 4] = [
+    ("1 hour", TimeDelta::hours(1)),
+    ("8 hours", TimeDelta::hours(8)),
+    ("1 day", TimeDelta::days(1)),
+    ("1 week", TimeDelta::weeks(1)),
+];
+
+fn parse_time(value: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%H:%M").ok()
+}
+
+/// Notification settings of one chat, changes are saved right away
+#[component]
+pub fn ChatSettingsPanel(chat_id: ObjectId, name: String, onclose: EventHandler) -> Element {
+    let all_settings = NOTIFICATIONS();
+    let settings = all_settings.chat(chat_id);
+    let quiet_hours = settings.quiet_hours.unwrap_or_default();
+    let quiet_start = quiet_hours.start.format("%H:%M").to_string();
+    let quiet_end = quiet_hours.end.format("%H:%M").to_string();
+    let muted_until = settings
+        .muted_until()
+        .map(|x| x.with_timezone(&Local).format("%b %-d, %H:%M").to_string());
+
+    let modes = [
+        (Mode::All, "All messages"),
+        (Mode::Mentions, "Only mentions of me"),
+        (Mode::Muted, "Nothing"),
+    ];
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black/50 flex items-center justify-center z-50",
+            div {
+                class: "bg-white rounded-lg shadow-xl w-full max-w-md mx-4",
+                div {
+                    class: "flex items-center justify-between p-6 border-b",
+                    h2 {
+                        class: "text-xl font-bold truncate",
+                        "Notifications for {name}"
+                    }
+                    button {
+                        class: "text-gray-500 hover:text-gray-700 text-2xl",
+                        onclick: move |_| onclose.call(()),
+                        "×"
+                    }
+                }
+                div {
+                    class: "p-6 space-y-6 text-sm",
+                    if !all_settings.enabled {
+                        p {
+                            class: "text-gray-500",
+                            "Desktop notifications are off, these settings still decide which chats get unread badges."
+                        }
+                    }
+                    fieldset {
+                        class: "space-y-2",
+                        legend {
+                            class: "font-semibold mb-2",
+                            "Notify me about"
+                        }
+                        for (mode, label) in modes {
+                            label {
+                                class: "flex items-center gap-2",
+                                input {
+                                    r#type: "radio",
+                                    name: "notification-mode",
+                                    checked: settings.mode == mode,
+                                    onchange: move |_| {
+                                        notifications::update_chat(chat_id, |x| x.mode = mode);
+                                    }
+                                }
+                                "{label}"
+                            }
+                        }
+                    }
+                    div {
+                        class: "space-y-2",
+                        div {
+                            class: "font-semibold",
+                            "Mute for a while"
+                        }
+                        if let Some(until) = muted_until {
+                            div {
+                                class: "flex items-center justify-between",
+                                span {
+                                    class: "text-gray-600",
+                                    "Muted until {until}"
+                                }
+                                button {
+                                    class: "px-3 py-1 border rounded hover:bg-gray-100",
+                                    onclick: move |_| {
+                                        notifications::update_chat(chat_id, |x| x.muted_until = None);
+                                    },
+                                    "Unmute"
+                                }
+                            }
+                        } else {
+                            div {
+                                class: "flex flex-wrap gap-2",
+                                for (label, duration) in MUTE_FOR {
+                                    button {
+                                        class: "px-3 py-1 border rounded hover:bg-gray-100",
+                                        onclick: move |_| {
+                                            notifications::update_chat(chat_id, |x| {
+                                                x.muted_until = Some(Utc::now() + duration);
+                                            });
+                                        },
+                                        "{label}"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    div {
+                        class: "space-y-2",
+                        label {
+                            class: "flex items-center gap-2 font-semibold",
+                            input {
+                                r#type: "checkbox",
+                                checked: settings.quiet_hours.is_some(),
+                                onchange: move |evt| {
+                                    let quiet_hours = evt.checked().then_some(quiet_hours);
+
+                                    notifications::update_chat(chat_id, |x| x.quiet_hours = quiet_hours);
+                                }
+                            }
+                            "Quiet hours"
+                        }
+                        if settings.quiet_hours.is_some() {
+                            div {
+                                class: "flex items-center gap-2",
+                                "From"
+                                input {
+                                    r#type: "time",
+                                    class: "border rounded px-2 py-1",
+                                    value: quiet_start,
+                                    onchange: move |evt| {
+                                        if let Some(start) = parse_time(&evt.value()) {
+                                            notifications::update_chat(chat_id, |x| {
+                                                x.quiet_hours = Some(QuietHours { start, ..quiet_hours });
+                                            });
+                                        }
+                                    }
+                                }
+                                "to"
+                                input {
+                                    r#type: "time",
+                                    class: "border rounded px-2 py-1",
+                                    value: quiet_end,
+                                    onchange: move |evt| {
+                                        if let Some(end) = parse_time(&evt.value()) {
+                                            notifications::update_chat(chat_id, |x| {
+                                                x.quiet_hours = Some(QuietHours { end, ..quiet_hours });
+                                            });
+                                        }
+                                    }
+                                }
+                            }
+                            p {
+                                class: "text-xs text-gray-500",
+                                "No desktop notifications in this time, unread badges still show."
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use chrono::Utc;
+use dioxus::prelude::*;
+use gloo_timers::future::TimeoutFuture;
+
+use crate::{
+    ws::{use_ws, ConnectionState},
+    CLAIMS, CONNECTION_STATE,
+};
+
+#[component]
+pub fn ConnectionBanner() -> Element {
+    let ws = use_ws();
+    let mut now_signal = use_signal(Utc::now);
+
+    // tick for the retry countdown
+    use_future(move || async move {
+        loop {
+            TimeoutFuture::new(1000).await;
+            now_signal.set(Utc::now());
+        }
+    });
+
+    if CLAIMS().is_none() {
+        return rsx! {};
+    }
+
+    let now = now_signal();
+
+    let (class, text, can_reconnect) = match CONNECTION_STATE() {
+        ConnectionState::Connected => return rsx! {},
+        ConnectionState::Connecting => (
+            "bg-yellow-100 text-yellow-800",
+            "Connecting...".to_string(),
+            false,
+        ),
+        ConnectionState::Reconnecting {
+            attempt,
+            next_retry_at,
+        } => {
+            let seconds = (next_retry_at - now).num_seconds().max(0);
+
+            (
+                "bg-red-100 text-red-800",
+                format!(
+                    "Connection lost, messages are queued until it is back. Retrying in {}s (attempt {})",
+                    seconds, attempt
+                ),
+                true,
+            )
+        }
+        ConnectionState::Offline => (
+            "bg-gray-200 text-gray-800",
+            "You are offline, messages are queued and sent once you reconnect".to_string(),
+            false,
+        ),
+    };
+
+    rsx! {
+        div {
+            class: "fixed top-0 inset-x-0 z-60 flex items-center justify-center gap-3 px-4 py-2 text-sm shadow {class}",
+            span {
+                "{text}"
+            }
+            if can_reconnect {
+                button {
+                    class: "px-2 py-1 border border-current rounded text-xs hover:bg-white/50",
+                    onclick: move |_| {
+                        ws.reconnect();
+                    },
+                    "Reconnect now"
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use dioxus::prelude::*;
+use dioxus_logger::tracing::warn;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    StatusCode, Url,
+};
+use serde::Deserialize;
+
+use crate::{config::use_config, CLAIMS, LINK_PREVIEWS};
+
+/// Page metadata returned by the backend unfurl endpoint
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Preview {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+}
+
+/// `None` when the backend definitely has nothing for the page, other
+/// errors like an expired token or rate limiting are worth retrying
+async fn unfurl(backend_url: &str, url: &str) -> anyhow::Result<Option<Preview>> {
+    let token = CLAIMS
+        .peek()
+        .as_ref()
+        .map(|x| x.token.clone())
+        .unwrap_or_default();
+
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        HeaderName::from_static("authorization"),
+        HeaderValue::from_str(format!("Bearer {}", token).as_str())?,
+    );
+
+    let endpoint = Url::parse_with_params(&format!("{}/unfurl", backend_url), &[("url", url)])?;
+
+    let client = reqwest::Client::new();
+    let res = client.get(endpoint).headers(headers).send().await?;
+
+    // not found, or a page the backend couldn't parse
+    if matches!(
+        res.status(),
+        StatusCode::NOT_FOUND | StatusCode::UNPROCESSABLE_ENTITY
+    ) {
+        return Ok(None);
+    }
+
+    let preview = res.error_for_status()?.json::<Preview>().await?;
+
+    Ok(Some(preview))
+}
+
+/// Preview card for `url`, renders nothing until (or unless) the backend
+/// knows something about the page
+#[component]
+pub fn LinkPreview(url: String) -> Element {
+    let config = use_config();
+    // like `Markdown`, only images the backend hosts are embedded so
+    // viewers never load from third party sites
+    let media = format!("{}/", config.backend_url);
+
+    use_effect(use_reactive!(|url| {
+        // in flight or done, failures are dropped so the link is retried the
+        // next time it is shown
+        if LINK_PREVIEWS.peek().contains_key(&url) {
+            return;
+        }
+
+        LINK_PREVIEWS.write().insert(url.clone(), None);
+
+        let backend_url = config.backend_url.clone();
+
+        spawn(async move {
+            match unfurl(&backend_url, &url).await {
+                Ok(Some(preview)) => {
+                    LINK_PREVIEWS.write().insert(url, Some(preview));
+                }
+                // stays cached as nothing to show
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("failed to unfurl {} {}", url, e);
+
+                    LINK_PREVIEWS.write().remove(&url);
+                }
+            }
+        });
+    }));
+
+    let preview = match LINK_PREVIEWS.read().get(&url) {
+        Some(Some(preview)) if preview.title.is_some() || preview.description.is_some() => {
+            preview.clone()
+        }
+        _ => return rsx! {},
+    };
+
+    let image = preview.image.filter(|x| x.starts_with(&media));
+
+    rsx! {
+        a {
+            href: "{url}",
+            target: "_blank",
+            rel: "noopener noreferrer",
+            class: "mt-1 flex max-w-md overflow-hidden rounded border border-gray-300 bg-white hover:bg-gray-50",
+            if let Some(image) = image {
+                img {
+                    src: image,
+                    alt: "",
+                    class: "w-24 h-24 object-cover flex-shrink-0"
+                }
+            }
+            div {
+                class: "p-2 min-w-0",
+                if let Some(title) = preview.title {
+                    div {
+                        class: "font-semibold truncate",
+                        "{title}"
+                    }
+                }
+                if let Some(description) = preview.description {
+                    div {
+                        class: "text-sm text-gray-600 line-clamp-2",
+                        "{description}"
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use dioxus::prelude::*;
+use uuid::Uuid;
+
+use super::link_preview::LinkPreview;
+use crate::{
+    config::use_config,
+    markdown::{
+        self,
+        highlight::{self, TokenKind},
+        Block, Inline,
+    },
+};
+
+fn token_class(kind: TokenKind) -> &'static str {
+    match kind {
+        TokenKind::Plain => "",
+        TokenKind::Keyword => "text-purple-400",
+        TokenKind::Type => "text-yellow-300",
+        TokenKind::Macro => "text-blue-300",
+        TokenKind::String => "text-green-400",
+        TokenKind::Number => "text-orange-300",
+        TokenKind::Comment => "text-gray-400 italic",
+        TokenKind::Key => "text-sky-300",
+        TokenKind::Variable => "text-pink-300",
+    }
+}
+
+#[component]
+fn CodeBlock(lang: Option<String>, code: String) -> Element {
+    let id = use_hook(|| format!("code-{}", Uuid::new_v4()));
+
+    let tokens = lang
+        .as_deref()
+        .and_then(|lang| highlight::highlight(lang, &code));
+
+    rsx! {
+        div {
+            class: "relative group",
+            pre {
+                class: "bg-gray-800 text-gray-100 rounded p-2 overflow-x-auto font-mono text-sm",
+                code {
+                    if let Some(tokens) = tokens {
+                        for token in tokens {
+                            span {
+                                class: token_class(token.kind),
+                                "{token.text}"
+                            }
+                        }
+                    } else {
+                        "{code}"
+                    }
+                }
+            }
+            button {
+                class: "absolute top-1 right-1 px-2 py-0.5 border rounded text-xs bg-gray-200 hover:bg-gray-100 opacity-0 group-hover:opacity-100",
+                id: "{id}",
+                onclick: move |_| {
+                    let eval = document::eval(r#"
+                        const id = await dioxus.recv();
+                        const msg = await dioxus.recv();
+
+                        await navigator.clipboard.writeText(msg);
+
+                        const elt = document.getElementById(id)
+
+                        elt.classList.remove("animate-copyCodeSuccess")
+                        void elt.offsetWidth;
+                        elt.classList.add("animate-copyCodeSuccess")
+                    "#);
+
+                    let _ = eval.send(id.clone());
+                    let _ = eval.send(code.clone());
+                },
+                "Copy"
+            }
+        }
+    }
+}
+
+/// Uploaded file, `url` is on our backend
+#[component]
+fn FileCard(name: String, url: String) -> Element {
+    rsx! {
+        a {
+            href: "{url}",
+            download: "{name}",
+            target: "_blank",
+            rel: "noopener noreferrer",
+            class: "my-1 flex max-w-xs items-center gap-2 rounded border border-gray-300 bg-white p-2 hover:bg-gray-50",
+            svg {
+                class: "w-6 h-6 flex-shrink-0 text-gray-500",
+                fill: "none",
+                stroke: "currentColor",
+                view_box: "0 0 24 24",
+                path {
+                    stroke_linecap: "round",
+                    stroke_linejoin: "round",
+                    stroke_width: 2,
+                    d: "M4 16v1a3 3 0 003 3h10a3 3 0 003-3v-1m-4-4l-4 4m0 0l-4-4m4 4V4"
+                }
+            }
+            span {
+                class: "truncate text-sm",
+                "{name}"
+            }
+        }
+    }
+}
+
+/// `media` is the url prefix of our backend, only files from there get
+/// embedded, anything else stays a link
+fn render_inline(nodes: &[Inline], media: &str) -> Element {
+    rsx! {
+        for node in nodes.iter() {
+            match node {
+                Inline::Text(text) => rsx! { "{text}" },
+                Inline::Bold(children) => rsx! {
+                    strong {
+                        {render_inline(children, media)}
+                    }
+                },
+                Inline::Italic(children) => rsx! {
+                    em {
+                        {render_inline(children, media)}
+                    }
+                },
+                Inline::Code(code) => rsx! {
+                    code {
+                        class: "bg-gray-200 rounded px-1 font-mono text-sm",
+                        "{code}"
+                    }
+                },
+                Inline::Link(url) => rsx! {
+                    a {
+                        href: "{url}",
+                        target: "_blank",
+                        rel: "noopener noreferrer",
+                        class: "text-blue-600 underline break-all",
+                        "{url}"
+                    }
+                },
+                Inline::Image { alt, url } if url.starts_with(media) => rsx! {
+                    a {
+                        href: "{url}",
+                        target: "_blank",
+                        rel: "noopener noreferrer",
+                        img {
+                            src: "{url}",
+                            alt: "{alt}",
+                            loading: "lazy",
+                            class: "my-1 max-h-64 max-w-full rounded border"
+                        }
+                    }
+                },
+                Inline::NamedLink { text, url } if url.starts_with(media) => rsx! {
+                    FileCard {
+                        name: text.clone(),
+                        url: url.clone()
+                    }
+                },
+                Inline::NamedLink { text, url } | Inline::Image { alt: text, url } => rsx! {
+                    a {
+                        href: "{url}",
+                        target: "_blank",
+                        rel: "noopener noreferrer",
+                        class: "text-blue-600 underline break-all",
+                        "{text}"
+                    }
+                },
+                Inline::LineBreak => rsx! { br {} },
+            }
+        }
+    }
+}
+
+fn render_blocks(blocks: &[Block], media: &str) -> Element {
+    rsx! {
+        for block in blocks.iter() {
+            match block {
+                Block::Paragraph(nodes) => rsx! {
+                    p {
+                        {render_inline(nodes, media)}
+                    }
+                },
+                Block::Code { lang, code } => rsx! {
+                    CodeBlock {
+                        lang: lang.clone(),
+                        code: code.clone()
+                    }
+                },
+                Block::Quote(children) => rsx! {
+                    blockquote {
+                        class: "border-l-4 border-gray-300 pl-2 text-gray-600",
+                        {render_blocks(children, media)}
+                    }
+                },
+                Block::List { ordered: true, items } => rsx! {
+                    ol {
+                        class: "list-decimal pl-6",
+                        for item in items.iter() {
+                            li {
+                                {render_inline(item, media)}
+                            }
+                        }
+                    }
+                },
+                Block::List { ordered: false, items } => rsx! {
+                    ul {
+                        class: "list-disc pl-6",
+                        for item in items.iter() {
+                            li {
+                                {render_inline(item, media)}
+                            }
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Message content rendered as markdown, text only ever ends up in text nodes
+#[component]
+pub fn Markdown(content: String) -> Element {
+    let config = use_config();
+    let blocks = use_memo(use_reactive!(|content| markdown::parse(&content)));
+    let media = format!("{}/", config.backend_url);
+
+    // only the first link gets a card
+    let preview = markdown::links(&blocks.read())
+        .first()
+        .map(|x| x.to_string());
+
+    rsx! {
+        div {
+            class: "space-y-1 break-words",
+            {render_blocks(&blocks.read(), &media)}
+        }
+        if let Some(url) = preview {
+            LinkPreview {
+                url
+            }
+        }
+    }
+}
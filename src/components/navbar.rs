@@ -1,16 +1,26 @@
 use dioxus::prelude::*;
-use shared::api::user::Claims;
 
-use crate::route::Route;
-
-#[derive(Clone)]
-pub struct Auth {
-    pub claims: Claims,
-    pub token: String,
-}
+use crate::{route::Route, FOCUS_CHAT, SELECTED_CHAT};
 
 #[component]
 pub fn NavBar() -> Element {
+    let navigator = use_navigator();
+    let route = use_route::<Route>();
+
+    // a notification was clicked, show its chat
+    use_effect(use_reactive!(|route| {
+        let Some(chat_id) = FOCUS_CHAT() else {
+            return;
+        };
+
+        *FOCUS_CHAT.write() = None;
+        *SELECTED_CHAT.write() = Some(chat_id);
+
+        if route != Route::Home {
+            navigator.push(Route::Home);
+        }
+    }));
+
     rsx! {
         Outlet::<Route> {}
     }
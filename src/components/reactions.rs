@@ -0,0 +1,123 @@
+use bson::oid::ObjectId;
+use dioxus::prelude::*;
+use dioxus_logger::tracing::warn;
+
+use crate::{
+    ws::{ext::ReactionUpdate, use_ws},
+    CHATS, CLAIMS, REACTIONS,
+};
+
+const PICKER: [&str; 12] = [
+    "👍", "👎", "❤️", "😂", "😮", "😢", "🎉", "🙏", "🔥", "👀", "✅", "🚀",
+];
+
+/// Reactions under a message, aggregated per emoji
+#[component]
+pub fn Reactions(chat_id: ObjectId, message_id: ObjectId) -> Element {
+    let ws = use_ws();
+    let mut show_picker_signal = use_signal(|| false);
+
+    let user_id = CLAIMS.read().as_ref().map(|x| x.claims.user_id);
+    let reactions = REACTIONS
+        .read()
+        .get(&message_id)
+        .cloned()
+        .unwrap_or_default();
+
+    let chats = CHATS.read();
+    let users = chats
+        .iter()
+        .find(|x| x.id == chat_id)
+        .map(|x| x.users.as_slice())
+        .unwrap_or_default();
+
+    // (emoji, display names, reacted by us) in order of the first reaction
+    let mut groups: Vec<(String, Vec<String>, bool)> = Vec::new();
+
+    for reaction in reactions {
+        let name = users
+            .iter()
+            .find(|x| x.id == reaction.user_id)
+            .map(|x| x.display_name.clone())
+            .unwrap_or_else(|| "Unknown".to_string());
+        let own = Some(reaction.user_id) == user_id;
+
+        match groups
+            .iter_mut()
+            .find(|(emoji, _, _)| *emoji == reaction.emoji)
+        {
+            Some((_, names, reacted)) => {
+                names.push(name);
+                *reacted |= own;
+            }
+            None => groups.push((reaction.emoji, vec![name], own)),
+        }
+    }
+
+    let mut toggle = move |emoji: String| {
+        show_picker_signal.set(false);
+
+        spawn(async move {
+            let update = ReactionUpdate {
+                chat_id,
+                message_id,
+                emoji,
+            };
+
+            if let Err(e) = ws.toggle_reaction(update).await {
+                warn!("failed to react {}", e);
+            }
+        });
+    };
+
+    let show_picker = show_picker_signal();
+    let empty = groups.is_empty();
+
+    rsx! {
+        div {
+            class: "relative flex flex-wrap items-center gap-1 mt-1",
+            for (emoji, names, reacted) in groups {
+                button {
+                    key: "{emoji}",
+                    class: if reacted {
+                        "px-2 py-0.5 rounded-full border text-xs border-blue-400 bg-blue-50"
+                    } else {
+                        "px-2 py-0.5 rounded-full border text-xs border-gray-300 bg-white hover:bg-gray-50"
+                    },
+                    title: names.join(", "),
+                    onclick: {
+                        let emoji = emoji.clone();
+
+                        move |_| toggle(emoji.clone())
+                    },
+                    "{emoji} {names.len()}"
+                }
+            }
+            button {
+                class: if empty {
+                    "hidden group-hover:block px-2 py-0.5 rounded-full border text-xs text-gray-500 hover:bg-gray-50"
+                } else {
+                    "px-2 py-0.5 rounded-full border text-xs text-gray-500 hover:bg-gray-50"
+                },
+                title: "Add reaction",
+                onclick: move |_| {
+                    show_picker_signal.set(!show_picker);
+                },
+                "+"
+            }
+            if show_picker {
+                div {
+                    class: "absolute left-0 top-full z-10 mt-1 grid grid-cols-6 gap-1 p-2 bg-white border rounded shadow-lg",
+                    for emoji in PICKER {
+                        button {
+                            key: "{emoji}",
+                            class: "p-1 rounded hover:bg-gray-100 text-lg",
+                            onclick: move |_| toggle(emoji.to_string()),
+                            "{emoji}"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
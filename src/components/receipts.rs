@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use bson::oid::ObjectId;
+use dioxus::prelude::*;
+use shared::models::chat::ChatSafe;
+
+/// Names shown before the rest is summed up
+const MAX_NAMES: usize = 3;
+
+/// Members other than `user_id` by the newest loaded message they have read,
+/// nobody is listed under their own message since writing it implies reading
+pub fn read_positions(
+    chat: &ChatSafe,
+    user_id: Option<ObjectId>,
+) -> HashMap<ObjectId, Vec<String>> {
+    let mut positions = HashMap::<ObjectId, Vec<String>>::new();
+
+    for member in chat.users.iter().filter(|x| Some(x.id) != user_id) {
+        // messages are sorted oldest first
+        let read = chat
+            .messages
+            .partition_point(|x| x.created_at <= member.last_message_seen_ts);
+
+        let message = match read.checked_sub(1).map(|i| &chat.messages[i]) {
+            Some(message) if message.creator != Some(member.id) => message,
+            _ => continue,
+        };
+
+        positions
+            .entry(message.id)
+            .or_default()
+            .push(member.display_name.clone());
+    }
+
+    positions
+}
+
+fn names(readers: &[String]) -> String {
+    match readers {
+        [] => String::new(),
+        [name] => name.clone(),
+        [rest @ .., last] if readers.len() <= MAX_NAMES => {
+            format!("{} and {}", rest.join(", "), last)
+        }
+        _ => format!(
+            "{} and {} others",
+            readers[..MAX_NAMES - 1].join(", "),
+            readers.len() - (MAX_NAMES - 1)
+        ),
+    }
+}
+
+/// Who read up to this message, `newest` gets the full "Seen by" row
+#[component]
+pub fn ReadReceipts(readers: Vec<String>, newest: bool) -> Element {
+    let mut readers = readers;
+    readers.sort();
+
+    let text = names(&readers);
+
+    rsx! {
+        if newest {
+            div {
+                class: "text-xs text-gray-500 text-right",
+                title: readers.join(", "),
+                "Seen by {text}"
+            }
+        } else {
+            div {
+                class: "text-[11px] text-gray-400 text-right",
+                title: "{text} read up to here",
+                "✓ {text}"
+            }
+        }
+    }
+}
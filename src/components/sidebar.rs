@@ -1,15 +1,18 @@
 use std::str::FromStr;
 
-use anyhow::anyhow;
 use bson::oid::ObjectId;
 use dioxus::prelude::*;
-use shared::api::{
-    chat::CreateRequest,
-    websocket::{WebsocketClientMessageData, WebsocketServerResData},
+use dioxus_logger::tracing::warn;
+use shared::api::chat::CreateRequest;
+
+use crate::{
+    components, notifications,
+    pages::home::UpdateHeight,
+    route::Route,
+    unread::{self, Unread},
+    ws::use_ws,
+    CHATS, CLAIMS, NOTIFICATIONS, USER,
 };
-use tokio::sync::oneshot;
-
-use crate::{components, pages::home::UpdateHeight, route::Route, CHATS, CLAIMS, USER};
 
 #[component]
 pub fn Sidebar(
@@ -17,38 +20,43 @@ pub fn Sidebar(
     update_height_signal: Signal<UpdateHeight>,
 ) -> Element {
     let mut new_modal_signal = use_signal(|| false);
+    let mut notifications_error_signal = use_signal::<Option<String>>(|| None);
     let selected_chat_id = selected_chat_id_signal();
     let chats = CHATS();
     let claims = CLAIMS();
     let user = USER();
 
-    let ws_channel = use_coroutine_handle::<(
-        WebsocketClientMessageData,
-        oneshot::Sender<Result<WebsocketServerResData, String>>,
-    )>();
-
-    let ws_request = move |req| -> oneshot::Receiver<_> {
-        let (tx, rx) = oneshot::channel();
-
-        ws_channel.send((req, tx));
+    let ws = use_ws();
 
-        rx
-    };
+    let user_id = claims.as_ref().map(|x| x.claims.user_id);
+    let notification_settings = NOTIFICATIONS();
 
     let chats_mapped = chats
         .into_iter()
         .map(|x| {
+            let settings = notification_settings.chat(x.id);
+            let unread = user_id.and_then(|user_id| unread::unread(&x, user_id, &settings));
+
             (
                 x.name,
                 x.id,
-                match Some(x.id) == selected_chat_id {
-                    true => "bg-blue-100 font-semibold",
+                match (Some(x.id) == selected_chat_id, unread.is_some()) {
+                    (true, _) => "bg-blue-100 font-semibold",
+                    (false, true) => "font-bold",
                     _ => "",
                 },
+                unread,
+                settings.muted(),
             )
         })
         .collect::<Vec<_>>();
 
+    let unread_chats = chats_mapped
+        .iter()
+        .filter(|(_, _, _, unread, _)| unread.is_some())
+        .map(|(_, id, _, _, _)| *id)
+        .collect::<Vec<_>>();
+
     use_effect(|| {
         document::eval(
             r#"
@@ -66,24 +74,70 @@ pub fn Sidebar(
 
     let logged_in = claims.is_some();
     let new_modal = new_modal_signal();
+    let notifications_enabled = notification_settings.enabled;
+    let notifications_error = notifications_error_signal();
 
     rsx! {
         aside {
             class: "w-64 bg-white border-r flex flex-col",
             div {
-                class: "p-4 font-bold text-lg border-b",
+                class: "p-4 font-bold text-lg border-b flex items-center justify-between",
                 "Chats"
+                if !unread_chats.is_empty() {
+                    button {
+                        class: "text-xs font-normal text-blue-600 hover:text-blue-800",
+                        onclick: move |_| {
+                            let unread_chats = unread_chats.clone();
+
+                            async move {
+                                let reads = unread_chats.into_iter().map(|x| ws.set_chat_read(x));
+
+                                for res in futures_util::future::join_all(reads).await {
+                                    if let Err(e) = res {
+                                        warn!("failed to mark chat as read {}", e);
+                                    }
+                                }
+                            }
+                        },
+                        "Mark all as read"
+                    }
+                }
             },
             ul {
                 class: "flex-1 overflow-y-auto",
-                for (name, id, cls) in chats_mapped {
+                for (name, id, cls, unread, muted) in chats_mapped {
                     li {
-                        class: "px-4 py-3 cursor-pointer hover:bg-blue-50 {cls}",
+                        class: "px-4 py-3 cursor-pointer hover:bg-blue-50 flex items-center justify-between gap-2 {cls}",
                         onclick: move |_| {
                             update_height_signal.set(UpdateHeight::GoDown);
                             selected_chat_id_signal.set(Some(id));
                         },
-                        "{name}"
+                        span {
+                            class: "truncate",
+                            "{name}"
+                        }
+                        match unread {
+                            Some(Unread { count: 0, .. }) => rsx! {
+                                span {
+                                    class: "w-2 h-2 rounded-full bg-blue-600 flex-shrink-0",
+                                    title: "New messages"
+                                }
+                            },
+                            Some(Unread { count, more }) => rsx! {
+                                span {
+                                    class: "px-2 rounded-full bg-blue-600 text-white text-xs font-semibold flex-shrink-0",
+                                    if more { "{count}+" } else { "{count}" }
+                                }
+                            },
+                            None if muted => rsx! {
+                                span {
+                                    class: "text-xs text-gray-400 flex-shrink-0",
+                                    title: "Muted",
+                                    "🔕"
+                                }
+                            },
+                            None => rsx! {},
+                        }
                     }
                 },
                 if logged_in {
@@ -118,6 +172,31 @@ pub fn Sidebar(
                         class: "text-blue-600 hover:text-blue-800 text-xs",
                         "Edit Profile"
                     }
+                    button {
+                        class: "text-blue-600 hover:text-blue-800 text-xs",
+                        onclick: move |_| async move {
+                            if notifications_enabled {
+                                notifications::disable();
+
+                                return;
+                            }
+
+                            match notifications::enable().await {
+                                Ok(_) => notifications_error_signal.set(None),
+                                Err(e) => notifications_error_signal.set(Some(e.to_string())),
+                            }
+                        },
+                        match notifications_enabled {
+                            true => "Turn off notifications",
+                            false => "Turn on notifications"
+                        }
+                    }
+                    if let Some(error) = notifications_error {
+                        div {
+                            class: "text-xs text-red-500 text-center",
+                            "{error}"
+                        }
+                    }
                 } else {
                     div {
                         class: "flex md:order-2 space-x-3 md:space-x-0 rtl:space-x-reverse",
@@ -177,17 +256,9 @@ pub fn Sidebar(
                                         let current_name = eval.recv::<String>().await.unwrap();
 
                                         if current_name.len() > 0 {
-                                            let new_chat_r = ws_request(WebsocketClientMessageData::CreateChat(CreateRequest {
+                                            let new_chat = ws.create_chat(CreateRequest {
                                                 name: current_name
-                                            })).await;
-
-                                            let new_chat = new_chat_r
-                                                .map_err(|err| anyhow!(err))
-                                                .and_then(|data| match data {
-                                                    Ok(WebsocketServerResData::CreateChat(chat)) => Ok(chat),
-                                                    Ok(_) => Err(anyhow!("unexpected response")),
-                                                    Err(e) => Err(anyhow!(e))
-                                                });
+                                            }).await;
 
                                             if let Ok(chat) = new_chat {
                                                 let chat_id = chat.id;
@@ -255,20 +326,10 @@ pub fn Sidebar(
                                         let id_r = ObjectId::from_str(code.as_str());
 
                                         if let Ok(id) = id_r {
-                                            let join_r = ws_request(WebsocketClientMessageData::JoinChat(id)).await;
-
-                                            let r = join_r
-                                                .map_err(|err| anyhow!(err))
-                                                .and_then(|data| match data {
-                                                    Ok(WebsocketServerResData::JoinChat(res)) => Ok(res),
-                                                    Ok(_) => Err(anyhow!("unexpected response")),
-                                                    Err(e) => Err(anyhow!(e))
-                                                });
-
-                                            if r.is_ok() {
+                                            if ws.join_chat(id).await.is_ok() {
                                                 new_modal_signal.set(false);
 
-                                                let _ = ws_request(WebsocketClientMessageData::GetChats).await;
+                                                let _ = ws.get_chats().await;
                                             }
                                         }
                                     }
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use bson::oid::ObjectId;
+use dioxus::prelude::*;
+use shared::models::{chat::ChatSafe, message::Message};
+
+use crate::{
+    components,
+    ws::{
+        sync::{self, MessageState},
+        use_ws,
+    },
+    CHATS, MESSAGE_STATES, REPLIES,
+};
+
+/// Replies can't nest deeper than this, guards against cycles
+const MAX_THREAD_DEPTH: usize = 64;
+
+const QUOTE_LENGTH: usize = 80;
+
+/// Oldest known ancestor of `message_id`
+pub fn thread_root(replies: &HashMap<ObjectId, ObjectId>, message_id: ObjectId) -> ObjectId {
+    let mut root = message_id;
+
+    for _ in 0..MAX_THREAD_DEPTH {
+        match replies.get(&root) {
+            Some(parent) => root = *parent,
+            None => break,
+        }
+    }
+
+    root
+}
+
+fn in_thread(replies: &HashMap<ObjectId, ObjectId>, message_id: ObjectId, root: ObjectId) -> bool {
+    let mut current = message_id;
+
+    for _ in 0..=MAX_THREAD_DEPTH {
+        if current == root {
+            return true;
+        }
+
+        match replies.get(&current) {
+            Some(parent) => current = *parent,
+            None => return false,
+        }
+    }
+
+    false
+}
+
+/// `(profile_image, display_name)` of whoever wrote `message`
+fn author(chat: &ChatSafe, message: &Message) -> (Option<String>, String) {
+    match message.creator {
+        Some(creator) => match chat.users.iter().find(|x| x.id == creator) {
+            Some(user) => (Some(user.profile_image.clone()), user.display_name.clone()),
+            None => (None, format!("Unknown({})", creator)),
+        },
+        None => (None, "System".to_string()),
+    }
+}
+
+fn is_deleted(message: &Message) -> bool {
+    MESSAGE_STATES.read().get(&message.id) == Some(&MessageState::Deleted)
+}
+
+/// One line preview of the message `parent_id` answers
+#[component]
+pub fn ReplyQuote(
+    chat_id: ObjectId,
+    parent_id: ObjectId,
+    onclick: EventHandler<MouseEvent>,
+) -> Element {
+    let chats = CHATS.read();
+    let chat = chats.iter().find(|x| x.id == chat_id);
+    let parent = chat.and_then(|chat| {
+        chat.messages
+            .iter()
+            .find(|x| x.id == parent_id)
+            .map(|x| (chat, x))
+    });
+
+    let (name, text) = match parent {
+        Some((_, message)) if is_deleted(message) => (None, "This message was deleted".to_string()),
+        Some((chat, message)) => {
+            let mut text = message
+                .content
+                .chars()
+                .take(QUOTE_LENGTH)
+                .collect::<String>();
+
+            if message.content.chars().count() > QUOTE_LENGTH {
+                text.push('…');
+            }
+
+            (Some(author(chat, message).1), text)
+        }
+        None => (None, "Original message not loaded".to_string()),
+    };
+
+    rsx! {
+        button {
+            class: "flex max-w-full gap-1 border-l-2 border-gray-400 pl-2 text-left text-xs text-gray-500 hover:text-gray-700",
+            r#type: "button",
+            onclick: move |evt| onclick.call(evt),
+            if let Some(name) = name {
+                span {
+                    class: "font-semibold flex-shrink-0",
+                    "{name}:"
+                }
+            }
+            span {
+                class: "truncate",
+                "{text}"
+            }
+        }
+    }
+}
+
+/// Side panel with the whole thread `message_id` belongs to, the start of
+/// the thread is fetched if it isn't loaded yet
+#[component]
+pub fn ThreadPanel(
+    chat_id: ObjectId,
+    message_id: ObjectId,
+    thread_signal: Signal<Option<ObjectId>>,
+    replying_signal: Signal<Option<ObjectId>>,
+) -> Element {
+    let ws = use_ws();
+    let mut status_signal = use_signal::<Option<String>>(|| None);
+
+    let replies = REPLIES();
+    let root = thread_root(&replies, message_id);
+
+    use_effect(use_reactive!(|chat_id, root| {
+        spawn(async move {
+            status_signal.set(Some("Loading...".to_string()));
+
+            let status = match sync::load_message(ws, chat_id, root).await {
+                Ok(true) => None,
+                Ok(false) => Some("The start of this thread is no longer available".to_string()),
+                Err(e) => Some(format!("Failed to load thread: {}", e)),
+            };
+
+            status_signal.set(status);
+        });
+    }));
+
+    let chats = CHATS();
+    let chat = match chats.into_iter().find(|x| x.id == chat_id) {
+        Some(chat) => chat,
+        None => return rsx! {},
+    };
+
+    let thread = chat
+        .messages
+        .iter()
+        .filter(|x| in_thread(&replies, x.id, root))
+        .map(|x| {
+            let (image, name) = author(&chat, x);
+
+            (x.clone(), image, name)
+        })
+        .collect::<Vec<_>>();
+
+    rsx! {
+        aside {
+            class: "w-80 bg-white border-l flex flex-col",
+            div {
+                class: "p-4 font-bold text-lg border-b flex justify-between items-center",
+                "Thread",
+                button {
+                    class: "px-2 py-1 border rounded text-xs hover:bg-gray-50",
+                    onclick: move |_| {
+                        thread_signal.set(None);
+                    },
+                    "×"
+                }
+            }
+            div {
+                class: "flex-1 overflow-y-auto p-4 space-y-4",
+                if let Some(status) = status_signal() {
+                    div {
+                        class: "text-sm text-gray-500",
+                        "{status}"
+                    }
+                }
+                for (message, image, name) in thread {
+                    div {
+                        key: "{message.id}",
+                        class: "flex items-start gap-3",
+                        components::avatar::Avatar {
+                            src: image,
+                            alt: name.clone(),
+                            size: components::avatar::Size::Small,
+                        }
+                        div {
+                            class: "min-w-0 flex-1",
+                            div {
+                                class: "font-semibold text-blue-600",
+                                "{name}"
+                            }
+                            if is_deleted(&message) {
+                                div {
+                                    class: "italic text-gray-400",
+                                    "This message was deleted"
+                                }
+                            } else {
+                                components::markdown::Markdown {
+                                    content: message.content.clone()
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            div {
+                class: "p-4 border-t",
+                button {
+                    class: "w-full bg-blue-600 text-white px-4 py-2 rounded hover:bg-blue-700",
+                    onclick: move |_| {
+                        replying_signal.set(Some(root));
+                    },
+                    "Reply in thread"
+                }
+            }
+        }
+    }
+}
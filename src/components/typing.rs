@@ -0,0 +1,50 @@
+use bson::oid::ObjectId;
+use chrono::Utc;
+use dioxus::prelude::*;
+
+use crate::{CHATS, TYPING};
+
+fn typing_text(names: &[String]) -> Option<String> {
+    match names {
+        [] => None,
+        [name] => Some(format!("{} is typing…", name)),
+        [first, second] => Some(format!("{} and {} are typing…", first, second)),
+        [first, second, third] => Some(format!("{}, {} and {} are typing…", first, second, third)),
+        _ => Some("Several people are typing…".to_string()),
+    }
+}
+
+/// Who else is writing in `chat_id`
+#[component]
+pub fn TypingIndicator(chat_id: ObjectId) -> Element {
+    let now = Utc::now();
+
+    let chats = CHATS.read();
+    let users = chats
+        .iter()
+        .find(|x| x.id == chat_id)
+        .map(|x| x.users.as_slice())
+        .unwrap_or_default();
+
+    let mut names = TYPING
+        .read()
+        .iter()
+        .filter(|((typing_chat_id, _), expires_at)| {
+            *typing_chat_id == chat_id && **expires_at > now
+        })
+        .filter_map(|((_, user_id), _)| users.iter().find(|x| x.id == *user_id))
+        .map(|x| x.display_name.clone())
+        .collect::<Vec<_>>();
+
+    // stable order, the map has none
+    names.sort();
+
+    rsx! {
+        div {
+            class: "h-5 px-4 text-xs italic text-gray-500 bg-white truncate",
+            if let Some(text) = typing_text(&names) {
+                "{text}"
+            }
+        }
+    }
+}
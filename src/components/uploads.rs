@@ -0,0 +1,63 @@
+use bson::oid::ObjectId;
+use dioxus::prelude::*;
+
+use crate::{upload::UploadStatus, UPLOADS};
+
+/// Uploads into `chat_id` that haven't been sent yet
+#[component]
+pub fn Uploads(chat_id: ObjectId) -> Element {
+    let uploads = UPLOADS
+        .read()
+        .iter()
+        .filter(|x| x.chat_id == chat_id)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    rsx! {
+        for upload in uploads {
+            div {
+                key: "{upload.id}",
+                class: "flex items-center gap-3 px-4 py-2 border-t bg-white text-sm",
+                span {
+                    class: "truncate max-w-xs",
+                    "{upload.name}"
+                }
+                match upload.status {
+                    UploadStatus::Uploading { loaded, total } => {
+                        let percent = match total > 0.0 {
+                            true => (loaded / total * 100.0).clamp(0.0, 100.0),
+                            false => 0.0,
+                        };
+
+                        rsx! {
+                            div {
+                                class: "flex-1 h-2 rounded bg-gray-200 overflow-hidden",
+                                div {
+                                    class: "h-full bg-blue-600 transition-all",
+                                    style: "width: {percent:.0}%"
+                                }
+                            }
+                            span {
+                                class: "text-xs text-gray-500 w-10 text-right",
+                                "{percent:.0}%"
+                            }
+                        }
+                    }
+                    UploadStatus::Failed(error) => rsx! {
+                        span {
+                            class: "flex-1 text-xs text-red-500",
+                            "{error}"
+                        }
+                        button {
+                            class: "px-2 py-1 border rounded text-xs hover:bg-gray-50",
+                            onclick: move |_| {
+                                UPLOADS.write().retain(|x| x.id != upload.id);
+                            },
+                            "Dismiss"
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
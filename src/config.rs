@@ -0,0 +1,279 @@
+use anyhow::{anyhow, bail, Context};
+use dioxus::prelude::*;
+use dioxus_logger::tracing::warn;
+use reqwest::Url;
+use serde::Deserialize;
+
+const CONFIG_PATH: &str = "/config.json";
+const META_BACKEND_URL: &str = "backend-url";
+const META_BACKEND_URL_WS: &str = "backend-url-ws";
+
+/// Used when neither `config.json` nor the meta tags set the backend
+static DEFAULT_BACKEND_URL: &str = match option_env!("BACKEND_URL") {
+    Some(x) => x,
+    None => "http://localhost:3030",
+};
+static DEFAULT_BACKEND_URL_WS: Option<&str> = option_env!("BACKEND_URL_WS");
+
+const DEFAULT_IMAGE_MAX_DIMENSION: u32 = 2048;
+const DEFAULT_IMAGE_QUALITY: f64 = 0.85;
+
+/// Backend endpoints and client settings, provided as context once loaded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub backend_url: String,
+    pub backend_url_ws: String,
+    /// longest side of uploaded images, larger ones are downscaled
+    pub image_max_dimension: u32,
+    /// encoder quality of re-encoded images, in `(0, 1]`
+    pub image_quality: f64,
+}
+
+#[derive(Deserialize, Default)]
+struct RawConfig {
+    backend_url: Option<String>,
+    backend_url_ws: Option<String>,
+    image_max_dimension: Option<u32>,
+    image_quality: Option<f64>,
+}
+
+pub fn use_config() -> Config {
+    use_context()
+}
+
+fn meta(name: &str) -> Option<String> {
+    web_sys::window()?
+        .document()?
+        .query_selector(&format!("meta[name=\"{}\"]", name))
+        .ok()??
+        .get_attribute("content")
+        .filter(|x| !x.is_empty())
+}
+
+fn origin() -> anyhow::Result<String> {
+    web_sys::window()
+        .context("failed to get window")?
+        .location()
+        .origin()
+        .map_err(|_| anyhow!("failed to get origin"))
+}
+
+/// `config.json` next to the app, `None` if it isn't served
+async fn fetch() -> anyhow::Result<Option<RawConfig>> {
+    let res = reqwest::Client::new()
+        .get(format!("{}{}", origin()?, CONFIG_PATH))
+        .send()
+        .await?;
+
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    Ok(Some(res.error_for_status()?.json::<RawConfig>().await?))
+}
+
+fn validate(url: &str, schemes: &[&str]) -> anyhow::Result<String> {
+    let parsed = Url::parse(url).with_context(|| format!("invalid backend url {}", url))?;
+
+    if !schemes.contains(&parsed.scheme()) {
+        bail!("backend url {} must use one of {}", url, schemes.join(", "));
+    }
+
+    if parsed.host().is_none() {
+        bail!("backend url {} has no host", url);
+    }
+
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+/// `http(s)://host` -> `ws(s)://host`
+fn derive_ws(backend_url: &str) -> String {
+    match backend_url.split_once("://") {
+        Some(("https", rest)) => format!("wss://{}", rest),
+        Some((_, rest)) => format!("ws://{}", rest),
+        None => backend_url.to_string(),
+    }
+}
+
+impl Config {
+    fn from_raw(raw: RawConfig) -> anyhow::Result<Self> {
+        // the compiled in ws url only goes with the compiled in http url
+        let (backend_url, backend_url_ws) = match raw.backend_url {
+            Some(x) => (x, raw.backend_url_ws),
+            None => (
+                DEFAULT_BACKEND_URL.to_string(),
+                raw.backend_url_ws
+                    .or(DEFAULT_BACKEND_URL_WS.map(String::from)),
+            ),
+        };
+
+        let backend_url = validate(&backend_url, &["http", "https"])?;
+        let backend_url_ws = validate(
+            &backend_url_ws.unwrap_or_else(|| derive_ws(&backend_url)),
+            &["ws", "wss"],
+        )?;
+
+        let image_max_dimension = raw
+            .image_max_dimension
+            .unwrap_or(DEFAULT_IMAGE_MAX_DIMENSION);
+        if image_max_dimension == 0 {
+            bail!("image_max_dimension must be positive");
+        }
+
+        let image_quality = raw.image_quality.unwrap_or(DEFAULT_IMAGE_QUALITY);
+        if image_quality <= 0.0 || image_quality > 1.0 {
+            bail!("image_quality {} must be in (0, 1]", image_quality);
+        }
+
+        Ok(Self {
+            backend_url,
+            backend_url_ws,
+            image_max_dimension,
+            image_quality,
+        })
+    }
+}
+
+/// Fills the backend urls `config.json` left out from the `<meta>` tags, a
+/// http url from the file never gets the ws url of the tags since a ws url
+/// only goes with the http url from the same source
+fn with_meta(raw: RawConfig, meta: impl Fn(&str) -> Option<String>) -> RawConfig {
+    if raw.backend_url.is_some() {
+        return raw;
+    }
+
+    RawConfig {
+        backend_url: meta(META_BACKEND_URL),
+        backend_url_ws: raw.backend_url_ws.or_else(|| meta(META_BACKEND_URL_WS)),
+        ..raw
+    }
+}
+
+/// Reads `config.json`, falling back to the `<meta>` tags and then to the
+/// compile time defaults
+pub async fn load() -> anyhow::Result<Config> {
+    let raw = match fetch().await {
+        Ok(Some(raw)) => raw,
+        Ok(None) => RawConfig::default(),
+        Err(e) => {
+            warn!("failed to load {} {}", CONFIG_PATH, e);
+
+            RawConfig::default()
+        }
+    };
+
+    Config::from_raw(with_meta(raw, meta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(backend_url: Option<&str>, backend_url_ws: Option<&str>) -> RawConfig {
+        RawConfig {
+            backend_url: backend_url.map(String::from),
+            backend_url_ws: backend_url_ws.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    fn no_meta(_: &str) -> Option<String> {
+        None
+    }
+
+    fn meta_tags(name: &str) -> Option<String> {
+        match name {
+            META_BACKEND_URL => Some("https://meta.example".to_string()),
+            META_BACKEND_URL_WS => Some("wss://meta.example/socket".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn derive_ws_follows_tls() {
+        assert_eq!(derive_ws("http://a.hr:3030"), "ws://a.hr:3030");
+        assert_eq!(derive_ws("https://a.hr/api"), "wss://a.hr/api");
+        assert_eq!(derive_ws("a.hr"), "a.hr");
+    }
+
+    #[test]
+    fn validate_checks_scheme_and_host() {
+        assert_eq!(
+            validate("https://a.hr/", &["http", "https"]).unwrap(),
+            "https://a.hr"
+        );
+        assert!(validate("ws://a.hr", &["http", "https"]).is_err());
+        assert!(validate("not a url", &["http", "https"]).is_err());
+        assert!(validate("data:text/plain,x", &["data"]).is_err());
+    }
+
+    #[test]
+    fn config_json_wins_over_meta() {
+        let config =
+            Config::from_raw(with_meta(raw(Some("http://file.example"), None), meta_tags)).unwrap();
+
+        assert_eq!(config.backend_url, "http://file.example");
+        // the meta ws url belongs to the meta http url, derived instead
+        assert_eq!(config.backend_url_ws, "ws://file.example");
+
+        let config = Config::from_raw(with_meta(
+            raw(
+                Some("http://file.example"),
+                Some("ws://file.example/socket"),
+            ),
+            meta_tags,
+        ))
+        .unwrap();
+
+        assert_eq!(config.backend_url_ws, "ws://file.example/socket");
+    }
+
+    #[test]
+    fn meta_fills_missing_urls() {
+        let config = Config::from_raw(with_meta(raw(None, None), meta_tags)).unwrap();
+
+        assert_eq!(config.backend_url, "https://meta.example");
+        assert_eq!(config.backend_url_ws, "wss://meta.example/socket");
+    }
+
+    #[test]
+    fn ws_url_is_derived_when_unset() {
+        let config =
+            Config::from_raw(with_meta(raw(Some("https://file.example/"), None), no_meta)).unwrap();
+
+        assert_eq!(config.backend_url, "https://file.example");
+        assert_eq!(config.backend_url_ws, "wss://file.example");
+    }
+
+    #[test]
+    fn defaults_apply_without_file_or_meta() {
+        let config = Config::from_raw(with_meta(RawConfig::default(), no_meta)).unwrap();
+        let backend_url = DEFAULT_BACKEND_URL.trim_end_matches('/');
+
+        assert_eq!(config.backend_url, backend_url);
+        assert_eq!(
+            config.backend_url_ws,
+            DEFAULT_BACKEND_URL_WS
+                .map(|x| x.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| derive_ws(backend_url))
+        );
+        assert_eq!(config.image_max_dimension, DEFAULT_IMAGE_MAX_DIMENSION);
+        assert_eq!(config.image_quality, DEFAULT_IMAGE_QUALITY);
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        assert!(Config::from_raw(raw(Some("ftp://a.hr"), None)).is_err());
+        assert!(Config::from_raw(raw(Some("http://a.hr"), Some("http://a.hr"))).is_err());
+        assert!(Config::from_raw(RawConfig {
+            image_quality: Some(0.0),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(Config::from_raw(RawConfig {
+            image_max_dimension: Some(0),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,658 @@
+//! Markdown subset used in messages, parsed into a tree that is rendered as
+//! rsx nodes so message content never reaches the page as html
+
+pub mod highlight;
+
+/// Nesting limit for quotes and emphasis, deeper markers stay plain text
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Inline>),
+    Code {
+        lang: Option<String>,
+        code: String,
+    },
+    Quote(Vec<Block>),
+    List {
+        ordered: bool,
+        items: Vec<Vec<Inline>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    Code(String),
+    /// always an http(s) url
+    Link(String),
+    /// `[text](url)`, always an http(s) url
+    NamedLink {
+        text: String,
+        url: String,
+    },
+    /// `![alt](url)`, always an http(s) url
+    Image {
+        alt: String,
+        url: String,
+    },
+    LineBreak,
+}
+
+/// Urls of all bare links in `blocks`, in order
+pub fn links(blocks: &[Block]) -> Vec<&str> {
+    fn inline<'a>(nodes: &'a [Inline], links: &mut Vec<&'a str>) {
+        for node in nodes {
+            match node {
+                Inline::Link(url) => links.push(url),
+                Inline::Bold(children) | Inline::Italic(children) => inline(children, links),
+                _ => {}
+            }
+        }
+    }
+
+    let mut links = Vec::new();
+
+    for block in blocks {
+        match block {
+            Block::Paragraph(nodes) => inline(nodes, &mut links),
+            Block::List { items, .. } => items.iter().for_each(|x| inline(x, &mut links)),
+            Block::Quote(children) => links.extend(self::links(children)),
+            Block::Code { .. } => {}
+        }
+    }
+
+    links
+}
+
+/// Text of `blocks` without markup on a single line, for places that can't
+/// render it like notifications
+pub fn plain(blocks: &[Block]) -> String {
+    fn inline(nodes: &[Inline], out: &mut String) {
+        for node in nodes {
+            match node {
+                Inline::Text(text) | Inline::Code(text) | Inline::Link(text) => out.push_str(text),
+                Inline::Bold(children) | Inline::Italic(children) => inline(children, out),
+                Inline::NamedLink { text, .. } => out.push_str(text),
+                Inline::Image { alt, .. } => {
+                    out.push_str("🖼 ");
+                    out.push_str(alt);
+                }
+                Inline::LineBreak => out.push(' '),
+            }
+        }
+    }
+
+    let mut parts = Vec::new();
+
+    for block in blocks {
+        let mut out = String::new();
+
+        match block {
+            Block::Paragraph(nodes) => inline(nodes, &mut out),
+            Block::Code { code, .. } => out.push_str(&code.replace('\n', " ")),
+            Block::Quote(children) => out.push_str(&plain(children)),
+            Block::List { items, .. } => {
+                for item in items {
+                    if !out.is_empty() {
+                        out.push(' ');
+                    }
+
+                    inline(item, &mut out);
+                }
+            }
+        }
+
+        parts.push(out);
+    }
+
+    parts.join(" ")
+}
+
+/// Whether `input` mentions `name` as `@name`, ignoring case, the mention
+/// has to end where the name does
+pub fn mentions(input: &str, name: &str) -> bool {
+    let name = name.trim().to_lowercase();
+
+    if name.is_empty() {
+        return false;
+    }
+
+    let input = input.to_lowercase();
+
+    input.match_indices('@').any(|(i, _)| {
+        let after = &input[i + 1..];
+        let before = input[..i].chars().next_back();
+
+        !before.is_some_and(char::is_alphanumeric)
+            && after.starts_with(&name)
+            && !after[name.len()..]
+                .chars()
+                .next()
+                .is_some_and(char::is_alphanumeric)
+    })
+}
+
+pub fn parse(input: &str) -> Vec<Block> {
+    parse_blocks(&input.lines().collect::<Vec<_>>(), 0)
+}
+
+/// `(ordered, content)` of a list item line
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(content) = line.strip_prefix(marker) {
+            return Some((false, content));
+        }
+    }
+
+    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+
+    if digits == 0 || digits > 9 {
+        return None;
+    }
+
+    let rest = &line[digits..];
+
+    rest.strip_prefix(". ")
+        .or_else(|| rest.strip_prefix(") "))
+        .map(|content| (true, content))
+}
+
+fn starts_block(line: &str, depth: usize) -> bool {
+    line.is_empty()
+        || line.starts_with("```")
+        || (depth < MAX_DEPTH && line.starts_with('>'))
+        || list_item(line).is_some()
+}
+
+fn parse_blocks(lines: &[&str], depth: usize) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i].trim_start();
+
+        if line.is_empty() {
+            i += 1;
+
+            continue;
+        }
+
+        if let Some(info) = line.strip_prefix("```") {
+            let lang = info.trim();
+            let lang = (!lang.is_empty()).then(|| lang.to_string());
+
+            let mut code = Vec::new();
+            i += 1;
+
+            // an unclosed fence runs to the end of the message
+            while i < lines.len() && !lines[i].trim_start().starts_with("```") {
+                code.push(lines[i]);
+                i += 1;
+            }
+            i += 1;
+
+            blocks.push(Block::Code {
+                lang,
+                code: code.join("\n"),
+            });
+
+            continue;
+        }
+
+        if depth < MAX_DEPTH && line.starts_with('>') {
+            let mut inner = Vec::new();
+
+            while let Some(quoted) = lines.get(i).and_then(|x| x.trim_start().strip_prefix('>')) {
+                inner.push(quoted.strip_prefix(' ').unwrap_or(quoted));
+                i += 1;
+            }
+
+            blocks.push(Block::Quote(parse_blocks(&inner, depth + 1)));
+
+            continue;
+        }
+
+        if let Some((ordered, _)) = list_item(line) {
+            let mut items = Vec::new();
+
+            while let Some((_, content)) = lines
+                .get(i)
+                .and_then(|x| list_item(x.trim_start()))
+                .filter(|(x, _)| *x == ordered)
+            {
+                items.push(parse_inline(content, depth));
+                i += 1;
+            }
+
+            blocks.push(Block::List { ordered, items });
+
+            continue;
+        }
+
+        let mut paragraph = parse_inline(line, depth);
+        i += 1;
+
+        while let Some(line) = lines
+            .get(i)
+            .map(|x| x.trim_start())
+            .filter(|x| !starts_block(x, depth))
+        {
+            paragraph.push(Inline::LineBreak);
+            paragraph.extend(parse_inline(line, depth));
+            i += 1;
+        }
+
+        blocks.push(Block::Paragraph(paragraph));
+    }
+
+    blocks
+}
+
+/// Content between `delim` at the start of `rest` and its closing `delim`
+fn delimited<'a>(rest: &'a str, delim: &str, prev: Option<char>) -> Option<&'a str> {
+    let after = rest.strip_prefix(delim)?;
+    let underscore = delim.starts_with('_');
+
+    if after.starts_with(char::is_whitespace)
+        || (underscore && prev.is_some_and(char::is_alphanumeric))
+    {
+        return None;
+    }
+
+    let mut offset = 0;
+
+    while let Some(i) = after[offset..].find(delim) {
+        let end = offset + i;
+        let inner = &after[..end];
+        let next = after[end + delim.len()..].chars().next();
+
+        let rejected = inner.is_empty()
+            || inner.ends_with(char::is_whitespace)
+            || (underscore && next.is_some_and(char::is_alphanumeric))
+            // a single marker doesn't close on half of a double one
+            || (delim.len() == 1 && (inner.ends_with(delim) || after[end + 1..].starts_with(delim)));
+
+        if !rejected {
+            return Some(inner);
+        }
+
+        offset = end + delim.len();
+    }
+
+    None
+}
+
+fn autolink(rest: &str, prev: Option<char>) -> Option<&str> {
+    if prev.is_some_and(char::is_alphanumeric)
+        || !(rest.starts_with("https://") || rest.starts_with("http://"))
+    {
+        return None;
+    }
+
+    let end = rest
+        .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '`'))
+        .unwrap_or(rest.len());
+
+    let url = rest[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'']);
+
+    // needs a host
+    url.split_once("://")
+        .filter(|(_, host)| !host.is_empty())
+        .map(|_| url)
+}
+
+fn http_url(url: &str) -> bool {
+    (url.starts_with("https://") || url.starts_with("http://"))
+        && url
+            .split_once("://")
+            .is_some_and(|(_, host)| !host.is_empty())
+        && !url.contains(char::is_whitespace)
+}
+
+/// `[text](url)` at the start of `rest` as `(text, url, length)`
+fn bracketed(rest: &str) -> Option<(&str, &str, usize)> {
+    let after = rest.strip_prefix('[')?;
+    let (text, after) = after.split_once("](")?;
+    let end = after.find(')')?;
+    let url = &after[..end];
+
+    if text.is_empty() || text.contains(['[', '\n']) || !http_url(url) {
+        return None;
+    }
+
+    Some((text, url, text.len() + url.len() + 4))
+}
+
+fn parse_inline(text: &str, depth: usize) -> Vec<Inline> {
+    let mut nodes = Vec::new();
+    let mut buffer = String::new();
+    let mut rest = text;
+    let mut prev = None;
+
+    let flush = |buffer: &mut String, nodes: &mut Vec<Inline>| {
+        if !buffer.is_empty() {
+            nodes.push(Inline::Text(std::mem::take(buffer)));
+        }
+    };
+
+    while let Some(c) = rest.chars().next() {
+        if c == '\\' {
+            if let Some(escaped) = rest[1..].chars().next().filter(char::is_ascii_punctuation) {
+                buffer.push(escaped);
+                rest = &rest[2..];
+                prev = Some(escaped);
+
+                continue;
+            }
+        }
+
+        if c == '`' {
+            if let Some(end) = rest[1..].find('`').filter(|x| *x > 0) {
+                flush(&mut buffer, &mut nodes);
+                nodes.push(Inline::Code(rest[1..end + 1].to_string()));
+                rest = &rest[end + 2..];
+                prev = Some('`');
+
+                continue;
+            }
+        }
+
+        if c == '!' || c == '[' {
+            let image = c == '!';
+
+            if let Some((text, url, len)) = bracketed(&rest[image as usize..]) {
+                flush(&mut buffer, &mut nodes);
+                nodes.push(match image {
+                    true => Inline::Image {
+                        alt: text.to_string(),
+                        url: url.to_string(),
+                    },
+                    false => Inline::NamedLink {
+                        text: text.to_string(),
+                        url: url.to_string(),
+                    },
+                });
+                rest = &rest[len + image as usize..];
+                prev = Some(')');
+
+                continue;
+            }
+        }
+
+        if let Some(url) = autolink(rest, prev) {
+            flush(&mut buffer, &mut nodes);
+            nodes.push(Inline::Link(url.to_string()));
+            rest = &rest[url.len()..];
+            prev = url.chars().last();
+
+            continue;
+        }
+
+        if depth < MAX_DEPTH && (c == '*' || c == '_') {
+            let emphasis = [("**", true), ("__", true), ("*", false), ("_", false)]
+                .into_iter()
+                .find_map(|(delim, bold)| delimited(rest, delim, prev).map(|x| (delim, bold, x)));
+
+            if let Some((delim, bold, inner)) = emphasis {
+                flush(&mut buffer, &mut nodes);
+
+                let children = parse_inline(inner, depth + 1);
+                nodes.push(match bold {
+                    true => Inline::Bold(children),
+                    false => Inline::Italic(children),
+                });
+
+                rest = &rest[inner.len() + delim.len() * 2..];
+                prev = c.into();
+
+                continue;
+            }
+        }
+
+        buffer.push(c);
+        rest = &rest[c.len_utf8()..];
+        prev = Some(c);
+    }
+
+    flush(&mut buffer, &mut nodes);
+
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(x: &str) -> Inline {
+        Inline::Text(x.to_string())
+    }
+
+    fn paragraph(input: &str) -> Vec<Inline> {
+        match parse(input).as_slice() {
+            [Block::Paragraph(nodes)] => nodes.clone(),
+            other => panic!("expected a single paragraph, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn html_stays_text() {
+        let input = "<script>alert(1)</script><img src=x onerror=alert(1)>";
+
+        assert_eq!(paragraph(input), vec![text(input)]);
+    }
+
+    #[test]
+    fn html_inside_markup_stays_text() {
+        assert_eq!(
+            paragraph("**<b>hi</b>** `<i>`"),
+            vec![
+                Inline::Bold(vec![text("<b>hi</b>")]),
+                text(" "),
+                Inline::Code("<i>".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_block_keeps_html() {
+        assert_eq!(
+            parse("```html\n<div onclick=\"x()\">\n```"),
+            vec![Block::Code {
+                lang: Some("html".to_string()),
+                code: "<div onclick=\"x()\">".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn only_http_links() {
+        for input in [
+            "javascript:alert(1)",
+            "data:text/html,<script>alert(1)</script>",
+            "vbscript:msgbox",
+            "[x](javascript:alert(1))",
+            "![x](data:image/svg+xml,<svg onload=alert(1)>)",
+        ] {
+            assert!(
+                !paragraph(input).iter().any(|x| matches!(
+                    x,
+                    Inline::Link(_) | Inline::NamedLink { .. } | Inline::Image { .. }
+                )),
+                "{} became a link",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn images_and_named_links() {
+        assert_eq!(
+            paragraph("![cat.png](https://a.com/media/1) [notes.pdf](https://a.com/media/2)"),
+            vec![
+                Inline::Image {
+                    alt: "cat.png".to_string(),
+                    url: "https://a.com/media/1".to_string(),
+                },
+                text(" "),
+                Inline::NamedLink {
+                    text: "notes.pdf".to_string(),
+                    url: "https://a.com/media/2".to_string(),
+                },
+            ]
+        );
+
+        // only bare links get previews
+        assert!(links(&parse("[x](https://a.com)")).is_empty());
+    }
+
+    #[test]
+    fn link_stops_at_quotes_and_brackets() {
+        assert_eq!(
+            paragraph("https://a.com\"onmouseover=\"x <https://b.com>"),
+            vec![
+                Inline::Link("https://a.com".to_string()),
+                text("\"onmouseover=\"x <"),
+                Inline::Link("https://b.com".to_string()),
+                text(">"),
+            ]
+        );
+    }
+
+    #[test]
+    fn link_drops_trailing_punctuation() {
+        assert_eq!(
+            paragraph("see https://example.com/a?b=1."),
+            vec![
+                text("see "),
+                Inline::Link("https://example.com/a?b=1".to_string()),
+                text("."),
+            ]
+        );
+    }
+
+    #[test]
+    fn link_needs_host() {
+        assert_eq!(paragraph("https://"), vec![text("https://")]);
+    }
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(
+            paragraph("**bold** *it* _it_ __bold__"),
+            vec![
+                Inline::Bold(vec![text("bold")]),
+                text(" "),
+                Inline::Italic(vec![text("it")]),
+                text(" "),
+                Inline::Italic(vec![text("it")]),
+                text(" "),
+                Inline::Bold(vec![text("bold")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_emphasis() {
+        assert_eq!(
+            paragraph("*a **b** c*"),
+            vec![Inline::Italic(vec![
+                text("a "),
+                Inline::Bold(vec![text("b")]),
+                text(" c"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn snake_case_is_not_italic() {
+        assert_eq!(
+            paragraph("some_var_name and __init__"),
+            vec![text("some_var_name and "), Inline::Bold(vec![text("init")]),]
+        );
+    }
+
+    #[test]
+    fn unclosed_markers_stay_text() {
+        assert_eq!(paragraph("**a `b _c"), vec![text("**a `b _c")]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(paragraph("\\*not\\* \\`"), vec![text("*not* `")]);
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(
+            parse("> quoted\n\n- a\n- b\n1. c\nline\nnext"),
+            vec![
+                Block::Quote(vec![Block::Paragraph(vec![text("quoted")])]),
+                Block::List {
+                    ordered: false,
+                    items: vec![vec![text("a")], vec![text("b")]],
+                },
+                Block::List {
+                    ordered: true,
+                    items: vec![vec![text("c")]],
+                },
+                Block::Paragraph(vec![text("line"), Inline::LineBreak, text("next")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_fence_runs_to_end() {
+        assert_eq!(
+            parse("```\n**x**"),
+            vec![Block::Code {
+                lang: None,
+                code: "**x**".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_bounded() {
+        let quotes = ">".repeat(10_000);
+        let emphasis = format!("{}x{}", "*".repeat(10_000), "*".repeat(10_000));
+
+        for input in [quotes, emphasis, "[".repeat(10_000), "`".repeat(10_000)] {
+            parse(&input);
+        }
+    }
+
+    #[test]
+    fn unicode() {
+        assert_eq!(
+            paragraph("**čćž** 🦀 _ž_"),
+            vec![
+                Inline::Bold(vec![text("čćž")]),
+                text(" 🦀 "),
+                Inline::Italic(vec![text("ž")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_drops_markup() {
+        assert_eq!(
+            plain(&parse(
+                "**hi** _there_\n\n> quoted `code`\n\n- one\n- [two](https://a.b)\n\n![cat](https://a.b/c.png)"
+            )),
+            "hi there quoted code one two 🖼 cat"
+        );
+    }
+
+    #[test]
+    fn mentions_whole_names() {
+        assert!(mentions("hey @Ana Horvat, look", "ana horvat"));
+        assert!(mentions("@ana", "Ana"));
+        assert!(!mentions("hey @anamarija", "Ana"));
+        assert!(!mentions("mail ana@ana.hr", "ana.hr"));
+        assert!(!mentions("hey ana", "Ana"));
+        assert!(!mentions("@", ""));
+    }
+}
@@ -0,0 +1,327 @@
+//! Small tokenizers for the languages people paste the most, every byte of
+//! the input ends up in exactly one token
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Plain,
+    Keyword,
+    Type,
+    Macro,
+    String,
+    Number,
+    Comment,
+    /// json object key
+    Key,
+    /// shell `$VAR`
+    Variable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Language {
+    Rust,
+    Json,
+    Shell,
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+const JSON_KEYWORDS: &[&str] = &["true", "false", "null"];
+
+const SHELL_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "in", "do", "done", "while", "until", "case",
+    "esac", "function", "return", "export", "local", "set", "unset",
+];
+
+fn language(lang: &str) -> Option<Language> {
+    match lang.to_lowercase().as_str() {
+        "rust" | "rs" => Some(Language::Rust),
+        "json" => Some(Language::Json),
+        "sh" | "bash" | "shell" | "zsh" | "fish" | "console" => Some(Language::Shell),
+        _ => None,
+    }
+}
+
+/// Tokens of `code`, `None` if `lang` isn't supported
+pub fn highlight(lang: &str, code: &str) -> Option<Vec<Token>> {
+    let language = language(lang)?;
+
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut rest = code;
+    let mut prev: Option<char> = None;
+
+    while !rest.is_empty() {
+        let (kind, len) = next_token(language, rest, prev);
+        let len = len.max(rest.chars().next().map_or(1, char::len_utf8));
+        let (text, tail) = rest.split_at(len);
+
+        match tokens.last_mut() {
+            Some(last) if last.kind == kind => last.text.push_str(text),
+            _ => tokens.push(Token {
+                kind,
+                text: text.to_string(),
+            }),
+        }
+
+        prev = text.chars().last();
+        rest = tail;
+    }
+
+    Some(tokens)
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Length of a quoted string starting at `rest`, unterminated strings run
+/// to the end of the line
+fn string_len(rest: &str, quote: char, escapes: bool) -> usize {
+    let mut escaped = false;
+
+    for (i, c) in rest.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if escapes => escaped = true,
+            '\n' => return i,
+            _ if c == quote => return i + 1,
+            _ => {}
+        }
+    }
+
+    rest.len()
+}
+
+/// `r"..."`, `r#"..."#`, `br"..."`
+fn raw_string_len(rest: &str) -> Option<usize> {
+    let after = rest.strip_prefix("br").or_else(|| rest.strip_prefix('r'))?;
+    let hashes = after.len() - after.trim_start_matches('#').len();
+    let body = after[hashes..].strip_prefix('"')?;
+    let close = format!("\"{}", "#".repeat(hashes));
+
+    let end = body.find(&close).map_or(body.len(), |x| x + close.len());
+
+    Some(rest.len() - body.len() + end)
+}
+
+fn number_len(rest: &str) -> usize {
+    rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+        .unwrap_or(rest.len())
+}
+
+fn next_token(language: Language, rest: &str, prev: Option<char>) -> (TokenKind, usize) {
+    let c = rest.chars().next().unwrap_or_default();
+    let line_end = rest.find('\n').unwrap_or(rest.len());
+    let after_ident = prev.is_some_and(is_ident);
+
+    match language {
+        Language::Rust => {
+            if rest.starts_with("//") {
+                return (TokenKind::Comment, line_end);
+            }
+
+            if let Some(body) = rest.strip_prefix("/*") {
+                return (
+                    TokenKind::Comment,
+                    body.find("*/").map_or(rest.len(), |x| x + 4),
+                );
+            }
+
+            if !after_ident {
+                if let Some(len) = raw_string_len(rest) {
+                    return (TokenKind::String, len);
+                }
+            }
+
+            if c == '"' {
+                return (TokenKind::String, string_len(rest, '"', true));
+            }
+
+            // char literal, a lone quote is a lifetime
+            if c == '\'' {
+                let len = string_len(rest, '\'', true);
+                let inner = rest[..len]
+                    .strip_prefix('\'')
+                    .and_then(|x| x.strip_suffix('\''))
+                    .unwrap_or_default();
+
+                if inner.chars().count() == 1 || (inner.starts_with('\\') && inner.len() <= 10) {
+                    return (TokenKind::String, len);
+                }
+            }
+        }
+        Language::Json => {
+            if c == '"' {
+                let len = string_len(rest, '"', true);
+                let key = rest[len..].trim_start().starts_with(':');
+
+                return match key {
+                    true => (TokenKind::Key, len),
+                    false => (TokenKind::String, len),
+                };
+            }
+        }
+        Language::Shell => {
+            if c == '#' && prev.is_none_or(char::is_whitespace) {
+                return (TokenKind::Comment, line_end);
+            }
+
+            if c == '"' {
+                return (TokenKind::String, string_len(rest, '"', true));
+            }
+
+            if c == '\'' {
+                return (TokenKind::String, string_len(rest, '\'', false));
+            }
+
+            if let Some(name) = rest.strip_prefix("${") {
+                return (
+                    TokenKind::Variable,
+                    name.find('}').map_or(rest.len(), |x| x + 3),
+                );
+            }
+
+            if let Some(name) = rest.strip_prefix('$') {
+                let len = match name.chars().next() {
+                    Some(x) if "?#@*!$0123456789".contains(x) => 1,
+                    _ => name.find(|c: char| !is_ident(c)).unwrap_or(name.len()),
+                };
+
+                return (TokenKind::Variable, len + 1);
+            }
+        }
+    }
+
+    if c.is_ascii_digit() && !after_ident {
+        return (TokenKind::Number, number_len(rest));
+    }
+
+    if c == '-' && language == Language::Json && rest[1..].starts_with(|x: char| x.is_ascii_digit())
+    {
+        return (TokenKind::Number, 1 + number_len(&rest[1..]));
+    }
+
+    if is_ident(c) {
+        // shell words like `my-script` or `file.txt` stay whole
+        let len = match language {
+            Language::Shell => rest
+                .find(|c: char| !(is_ident(c) || c == '-' || c == '.'))
+                .unwrap_or(rest.len()),
+            _ => rest.find(|c: char| !is_ident(c)).unwrap_or(rest.len()),
+        };
+        let word = &rest[..len];
+
+        let keywords = match language {
+            Language::Rust => RUST_KEYWORDS,
+            Language::Json => JSON_KEYWORDS,
+            Language::Shell => SHELL_KEYWORDS,
+        };
+
+        if after_ident || !keywords.contains(&word) {
+            if language == Language::Rust && !after_ident {
+                if rest[len..].starts_with('!') && !rest[len..].starts_with("!=") {
+                    return (TokenKind::Macro, len + 1);
+                }
+
+                if word.starts_with(char::is_uppercase) {
+                    return (TokenKind::Type, len);
+                }
+            }
+
+            return (TokenKind::Plain, len);
+        }
+
+        return (TokenKind::Keyword, len);
+    }
+
+    (TokenKind::Plain, c.len_utf8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(lang: &str, code: &str) -> Vec<(TokenKind, String)> {
+        highlight(lang, code)
+            .unwrap()
+            .into_iter()
+            .filter(|x| x.kind != TokenKind::Plain)
+            .map(|x| (x.kind, x.text))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_every_byte() {
+        for (lang, code) in [
+            (
+                "rust",
+                "fn f<'a, 'b>() { let s = r#\"a\"# ; 'x'; '\\n'; 'a: loop {} /* 🦀",
+            ),
+            ("json", "{\"a\": [1, -2.5e3, true, \"\\\"\"], \"b"),
+            ("sh", "echo \"$HOME\" '${x}' # done\n${unterminated"),
+        ] {
+            let tokens = highlight(lang, code).unwrap();
+
+            assert_eq!(
+                tokens.iter().map(|x| x.text.as_str()).collect::<String>(),
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_language() {
+        assert_eq!(highlight("brainfuck", "+++"), None);
+    }
+
+    #[test]
+    fn rust() {
+        assert_eq!(
+            kinds("rs", "let x: Vec<u8> = vec![1]; // hi"),
+            vec![
+                (TokenKind::Keyword, "let".to_string()),
+                (TokenKind::Type, "Vec".to_string()),
+                (TokenKind::Macro, "vec!".to_string()),
+                (TokenKind::Number, "1".to_string()),
+                (TokenKind::Comment, "// hi".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            kinds("json", "{\"a\": \"b\", \"c\": null}"),
+            vec![
+                (TokenKind::Key, "\"a\"".to_string()),
+                (TokenKind::String, "\"b\"".to_string()),
+                (TokenKind::Key, "\"c\"".to_string()),
+                (TokenKind::Keyword, "null".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shell() {
+        assert_eq!(
+            kinds("bash", "if [ -f a#b ]; then echo $HOME; fi # end"),
+            vec![
+                (TokenKind::Keyword, "if".to_string()),
+                (TokenKind::Keyword, "then".to_string()),
+                (TokenKind::Variable, "$HOME".to_string()),
+                (TokenKind::Keyword, "fi".to_string()),
+                (TokenKind::Comment, "# end".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Desktop notifications for messages that arrive while their chat isn't in
+//! view, opt-in and filtered by per chat settings that also decide badging
+
+use anyhow::{anyhow, bail};
+use bson::oid::ObjectId;
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use dioxus::prelude::*;
+use dioxus_logger::tracing::warn;
+use gloo_timers::future::TimeoutFuture;
+use serde::{Deserialize, Serialize};
+use shared::models::message::Message;
+
+use crate::{auth, markdown, CHATS, CLAIMS, FOCUS_CHAT, NOTIFICATIONS, SELECTED_CHAT};
+
+/// Characters of the message shown in the notification
+const MAX_BODY: usize = 120;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Mode {
+    #[default]
+    All,
+    /// only messages that mention us by name
+    Mentions,
+    Muted,
+}
+
+/// Local time range without notifications, wraps past midnight when `end`
+/// is before `start`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Default for QuietHours {
+    fn default() -> Self {
+        Self {
+            start: NaiveTime::from_hms_opt(22, 0, 0).unwrap_or_default(),
+            end: NaiveTime::from_hms_opt(8, 0, 0).unwrap_or_default(),
+        }
+    }
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        match self.start <= self.end {
+            true => self.start <= time && time < self.end,
+            false => time >= self.start || time < self.end,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatSettings {
+    pub chat_id: ObjectId,
+    pub mode: Mode,
+    /// muted regardless of `mode` until then
+    pub muted_until: Option<DateTime<Utc>>,
+    /// still badged, just not notified
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl ChatSettings {
+    fn new(chat_id: ObjectId) -> Self {
+        Self {
+            chat_id,
+            mode: Mode::All,
+            muted_until: None,
+            quiet_hours: None,
+        }
+    }
+
+    /// Mute that is still running
+    pub fn muted_until(&self) -> Option<DateTime<Utc>> {
+        self.muted_until.filter(|x| *x > Utc::now())
+    }
+
+    pub fn muted(&self) -> bool {
+        self.mode == Mode::Muted || self.muted_until().is_some()
+    }
+
+    /// Whether a message with `content` should be badged, `name` is ours
+    pub fn wants(&self, content: &str, name: &str) -> bool {
+        match self.mode {
+            _ if self.muted() => false,
+            Mode::Mentions => markdown::mentions(content, name),
+            _ => true,
+        }
+    }
+
+    pub fn quiet(&self) -> bool {
+        self.quiet_hours
+            .is_some_and(|x| x.contains(Local::now().time()))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// the user opted in and the browser granted permission
+    pub enabled: bool,
+    /// only chats that differ from the defaults
+    pub chats: Vec<ChatSettings>,
+}
+
+impl Settings {
+    pub fn chat(&self, chat_id: ObjectId) -> ChatSettings {
+        self.chats
+            .iter()
+            .find(|x| x.chat_id == chat_id)
+            .cloned()
+            .unwrap_or_else(|| ChatSettings::new(chat_id))
+    }
+}
+
+/// What the notification script needs to show one message
+#[derive(Serialize)]
+struct Notification {
+    tag: String,
+    title: String,
+    body: String,
+    icon: Option<String>,
+    /// the chat is open, only notify while the tab is hidden
+    selected: bool,
+}
+
+/// Every user gets their own settings so a shared browser never inherits
+/// someone else's opt-in and mutes
+fn settings_key(user_id: ObjectId) -> String {
+    format!("wpp-notifications-{}", user_id)
+}
+
+fn user_id() -> Option<ObjectId> {
+    CLAIMS.peek().as_ref().map(|x| x.claims.user_id)
+}
+
+/// Settings the logged in user stored on a previous visit, used to
+/// initialize `NOTIFICATIONS` and to reload it when the user changes
+pub fn restore() -> Settings {
+    let Some(user_id) = user_id() else {
+        return Settings::default();
+    };
+
+    auth::local_storage()
+        .ok()
+        .and_then(|x| x.get_item(&settings_key(user_id)).ok()?)
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default()
+}
+
+fn update(f: impl FnOnce(&mut Settings)) {
+    let Some(user_id) = user_id() else {
+        return;
+    };
+
+    let settings = &mut (*NOTIFICATIONS.write());
+
+    f(settings);
+
+    let saved = serde_json::to_string(settings)
+        .map_err(|e| anyhow!(e))
+        .and_then(|x| {
+            auth::local_storage()?
+                .set_item(&settings_key(user_id), &x)
+                .map_err(|_| anyhow!("failed to update local storage"))
+        });
+
+    if let Err(e) = saved {
+        warn!("failed to save notification settings {}", e);
+    }
+}
+
+/// Asks the browser for permission and opts in if it is granted
+pub async fn enable() -> anyhow::Result<()> {
+    let mut eval = document::eval(
+        r#"
+        if ("Notification" in window) {
+            dioxus.send(await Notification.requestPermission())
+        } else {
+            dioxus.send("unsupported")
+        }
+        "#,
+    );
+
+    match eval.recv::<String>().await?.as_str() {
+        "granted" => {
+            update(|x| x.enabled = true);
+
+            Ok(())
+        }
+        "unsupported" => bail!("notifications aren't supported by this browser"),
+        _ => bail!("notifications are blocked for this site"),
+    }
+}
+
+pub fn disable() {
+    update(|x| x.enabled = false);
+}
+
+pub fn update_chat(chat_id: ObjectId, f: impl FnOnce(&mut ChatSettings)) {
+    update(|settings| {
+        let mut chat = settings.chat(chat_id);
+
+        f(&mut chat);
+
+        settings.chats.retain(|x| x.chat_id != chat_id);
+
+        if chat != ChatSettings::new(chat_id) {
+            settings.chats.push(chat);
+        }
+    });
+}
+
+/// Lifts timed mutes once they run out so badges come back right away, and
+/// drops them from the stored settings
+pub fn use_mute_expiry() {
+    let mut task_signal = use_signal::<Option<Task>>(|| None);
+
+    use_effect(move || {
+        let next = NOTIFICATIONS()
+            .chats
+            .iter()
+            .filter_map(|x| x.muted_until)
+            .min();
+
+        if let Some(task) = task_signal.take() {
+            task.cancel();
+        }
+
+        let Some(next) = next else {
+            return;
+        };
+
+        let wait = (next - Utc::now())
+            .num_milliseconds()
+            .clamp(0, u32::MAX as i64) as u32;
+
+        task_signal.set(Some(spawn(async move {
+            TimeoutFuture::new(wait).await;
+
+            let now = Utc::now();
+
+            update(|settings| {
+                for chat in settings.chats.iter_mut() {
+                    if chat.muted_until.is_some_and(|x| x <= now) {
+                        chat.muted_until = None;
+                    }
+                }
+
+                settings
+                    .chats
+                    .retain(|x| *x != ChatSettings::new(x.chat_id));
+            });
+        })));
+    });
+}
+
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(MAX_BODY) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+/// Notifies about a message others sent if the chat's settings allow it,
+/// clicking it opens the chat
+pub(crate) fn new_message(message: &Message, user_id: ObjectId) {
+    let settings = {
+        let settings = NOTIFICATIONS.peek();
+
+        if !settings.enabled || message.creator == Some(user_id) {
+            return;
+        }
+
+        settings.chat(message.chat_id)
+    };
+
+    if settings.quiet() {
+        return;
+    }
+
+    let notification = {
+        let chats = CHATS.peek();
+
+        let Some(chat) = chats.iter().find(|x| x.id == message.chat_id) else {
+            return;
+        };
+
+        let Some(me) = chat.users.iter().find(|x| x.id == user_id) else {
+            return;
+        };
+
+        if !settings.wants(&message.content, &me.display_name) {
+            return;
+        }
+
+        let sender = message
+            .creator
+            .and_then(|id| chat.users.iter().find(|x| x.id == id));
+
+        Notification {
+            tag: chat.id.to_string(),
+            title: match sender {
+                Some(sender) => format!("{} in {}", sender.display_name, chat.name),
+                None => chat.name.clone(),
+            },
+            body: truncate(&markdown::plain(&markdown::parse(&message.content))),
+            icon: sender
+                .map(|x| x.profile_image.clone())
+                .filter(|x| !x.is_empty()),
+            selected: *SELECTED_CHAT.peek() == Some(chat.id),
+        }
+    };
+
+    let chat_id = message.chat_id;
+
+    spawn(async move {
+        let mut eval = document::eval(include_str!("../js/notification.js"));
+
+        if let Err(e) = eval.send(notification) {
+            warn!("failed to show notification {}", e);
+
+            return;
+        }
+
+        if let Ok(true) = eval.recv::<bool>().await {
+            *FOCUS_CHAT.write() = Some(chat_id);
+        }
+    });
+}
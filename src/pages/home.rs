@@ -1,16 +1,25 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
 use bson::oid::ObjectId;
 use dioxus::prelude::*;
 use dioxus_logger::tracing::{self, info};
-use tokio::sync::oneshot;
 
 use shared::api::{
     message::{CreateRequest, GetRequest},
-    websocket::{MediaSoupMessage, WebsocketClientMessageData, WebsocketServerResData},
+    websocket::MediaSoupMessage,
 };
 
-use crate::{components, CHATS, USER};
+use crate::{
+    components,
+    ws::{
+        ext::{MessageDelete, MessageEdit},
+        outbox::{self, OutboxStatus},
+        sync::{self, MessageState},
+        typing, use_ws,
+    },
+    CHATS, MESSAGE_STATES, OUTBOX, PRESENCE, REPLIES, SELECTED_CHAT, USER,
+};
 
 #[derive(Clone)]
 pub enum UpdateHeight {
@@ -21,27 +30,53 @@ pub enum UpdateHeight {
 
 pub fn Home() -> Element {
     // defined signals
-    let selected_chat_id_signal = use_signal::<Option<ObjectId>>(|| None);
-    let ws_channel = use_coroutine_handle::<(
-        WebsocketClientMessageData,
-        oneshot::Sender<Result<WebsocketServerResData, String>>,
-    )>();
+    let selected_chat_id_signal = SELECTED_CHAT.signal();
+    let ws = use_ws();
     let mut update_height_signal = use_signal(|| UpdateHeight::CheckNeed);
     let mut show_users_signal = use_signal(|| false);
+    let mut show_chat_settings_signal = use_signal(|| false);
 
     let mut show_media_signal = use_signal(|| (false, None));
-
-    let ws_request = move |req| -> oneshot::Receiver<_> {
-        let (tx, rx) = oneshot::channel();
-
-        ws_channel.send((req, tx));
-
-        rx
-    };
+    // message being edited and its new content
+    let mut editing_signal = use_signal::<Option<(ObjectId, String)>>(|| None);
+    let mut message_error_signal = use_signal::<Option<(ObjectId, String)>>(|| None);
+    // message the thread panel was opened from
+    let mut thread_signal = use_signal::<Option<ObjectId>>(|| None);
+    let mut replying_signal = use_signal::<Option<ObjectId>>(|| None);
+    let mut typing_notifier = typing::use_typing_notifier();
 
     // dependant signals
     let selected_chat_id = selected_chat_id_signal();
     let chats = CHATS();
+    let user = USER();
+    // local echoes by their temporary message id
+    let pending_messages = OUTBOX()
+        .into_iter()
+        .map(|x| (x.message_id, x))
+        .collect::<HashMap<_, _>>();
+    let message_states = MESSAGE_STATES();
+    let editing = editing_signal();
+    let message_error = message_error_signal();
+    let replies = REPLIES();
+    let presence = PRESENCE();
+    let show_chat_settings = show_chat_settings_signal();
+    let thread = thread_signal();
+    let replying = replying_signal();
+
+    let mut reply_counts = HashMap::<ObjectId, usize>::new();
+    for parent in replies.values() {
+        *reply_counts.entry(*parent).or_default() += 1;
+    }
+
+    // threads, replies, typing and chat settings don't carry over to another chat
+    use_effect(move || {
+        selected_chat_id_signal();
+
+        thread_signal.set(None);
+        replying_signal.set(None);
+        show_chat_settings_signal.set(false);
+        typing_notifier.stop();
+    });
     let show_users = show_users_signal();
 
     let selected_chat = chats
@@ -57,6 +92,19 @@ pub fn Home() -> Element {
             )
         });
 
+    // members by the message they have read up to
+    let read_positions = selected_chat
+        .as_ref()
+        .map(|(chat, _)| components::receipts::read_positions(chat, user.as_ref().map(|x| x.id)))
+        .unwrap_or_default();
+    let newest_message_id = selected_chat
+        .as_ref()
+        .and_then(|(chat, _)| chat.messages.last().map(|x| x.id));
+    let chat_settings = selected_chat
+        .as_ref()
+        .filter(|_| show_chat_settings)
+        .map(|(chat, _)| (chat.id, chat.name.clone()));
+
     let _ = use_effect(move || {
         // dependant signals
         let chats = CHATS();
@@ -99,33 +147,22 @@ pub fn Home() -> Element {
                                 if let Some(chat_user) = chat.users.iter().find(|x| x.id == user.id)
                                 {
                                     if chat_user.last_message_seen_ts != chat.last_message_ts {
-                                        let _ = ws_request(
-                                            WebsocketClientMessageData::SetChatRead(chat.id),
-                                        )
-                                        .await;
+                                        let _ = ws.set_chat_read(chat.id).await;
                                     }
                                 }
                             }
 
                             let ts = chat.messages.get(0).map(|x| x.created_at);
 
-                            let rx =
-                                ws_request(WebsocketClientMessageData::GetMessages(GetRequest {
+                            let res = ws
+                                .get_messages(GetRequest {
                                     chat_id: chat.id,
                                     last_message_ts: ts,
-                                }));
-
-                            let mut messages = match rx.await {
-                                Ok(data) => match data {
-                                    Ok(WebsocketServerResData::GetMessages(messages)) => messages,
-                                    Err(e) => {
-                                        info!("{}", e);
-
-                                        Vec::new()
-                                    }
-                                    _ => Vec::new(),
-                                },
+                                })
+                                .await;
 
+                            let messages = match res {
+                                Ok(messages) => messages,
                                 Err(e) => {
                                     info!("{}", e);
 
@@ -137,14 +174,8 @@ pub fn Home() -> Element {
                                 return;
                             }
 
-                            let mut chats = CHATS.write();
-                            let chat_o = chats.iter_mut().find(|x| x.id == chat.id);
-
-                            if let Some(chat_m) = chat_o {
-                                messages.extend(chat.messages.into_iter());
-                                chat_m.messages = messages;
-                                update_height_signal.set(UpdateHeight::GoTo(current_height));
-                            }
+                            sync::merge_messages(chat.id, messages);
+                            update_height_signal.set(UpdateHeight::GoTo(current_height));
                         }
                         UpdateHeight::GoDown => {
                             let _ = document::eval(
@@ -195,6 +226,7 @@ pub fn Home() -> Element {
     };
 
     rsx! {
+        components::connection_banner::ConnectionBanner {}
         div {
             class: "flex h-screen bg-gray-100",
             components::sidebar::Sidebar {
@@ -211,6 +243,13 @@ pub fn Home() -> Element {
                             "{chat.name}"
                         }
                         div {
+                            button {
+                                class: "px-3 py-1 border rounded text-sm hover:bg-gray-100 m-2",
+                                onclick: move |_| {
+                                    show_chat_settings_signal.set(true);
+                                },
+                                "Notifications"
+                            }
                             if !show_media {
                                 button {
                                     class: "px-3 py-1 bg-green-600 text-white rounded text-sm hover:bg-green-700 m-2",
@@ -218,9 +257,13 @@ pub fn Home() -> Element {
                                         to_owned![user_map];
 
                                         async move {
-                                            let res = ws_request(WebsocketClientMessageData::MS(MediaSoupMessage::SetRoom(chat.id)));
+                                            // room setup negotiates transports, give it more time
+                                            let res = ws
+                                                .with_timeout(Duration::from_secs(30))
+                                                .media_soup(MediaSoupMessage::SetRoom(chat.id))
+                                                .await;
 
-                                            match res.await {
+                                            match res {
                                                 Ok(_) => {
                                                     *show_media_signal.write() = (true, selected_chat_id);
 
@@ -312,9 +355,9 @@ pub fn Home() -> Element {
                                     aria_label: "Leave call",
                                     onclick: move |_| {
                                         async move {
-                                            let res = ws_request(WebsocketClientMessageData::MS(MediaSoupMessage::LeaveRoom));
+                                            let res = ws.media_soup(MediaSoupMessage::LeaveRoom).await;
 
-                                            match res.await {
+                                            match res {
                                                 Ok(_) => {
                                                     *show_media_signal.write() = (false, selected_chat_id);
                                                 },
@@ -407,9 +450,9 @@ pub fn Home() -> Element {
                             update_height_signal.set(UpdateHeight::CheckNeed);
                         },
                         id: "chat-messages",
-                        for message in &chat.messages {
+                        for message in chat.messages.iter().cloned() {
                             div {
-                                class: "flex items-start gap-3",
+                                class: "flex items-start gap-3 group",
                                 if let Some(creator) = message.creator {
                                     if let Some(chat_user) = chat.users.iter().find(|user| user.id == creator) {
                                         components::avatar::Avatar {
@@ -444,15 +487,222 @@ pub fn Home() -> Element {
                                     }
                                 }
                                 div {
-                                    div {
-                                        "{message.content}"
+                                    class: "min-w-0 flex-1",
+                                    if let Some(parent_id) = replies.get(&message.id).copied().or_else(|| pending_messages.get(&message.id).and_then(|x| x.parent_id)) {
+                                        components::thread::ReplyQuote {
+                                            chat_id: chat.id,
+                                            parent_id,
+                                            onclick: move |_| {
+                                                thread_signal.set(Some(message.id));
+                                            }
+                                        }
+                                    }
+                                    if message_states.get(&message.id) == Some(&MessageState::Deleted) {
+                                        div {
+                                            class: "italic text-gray-400",
+                                            "This message was deleted"
+                                        }
+                                    } else if let Some(pending) = pending_messages.get(&message.id).cloned() {
+                                        div {
+                                            class: "opacity-60",
+                                            components::markdown::Markdown {
+                                                content: message.content.clone()
+                                            }
+                                        }
+                                        if let OutboxStatus::Failed(e) = &pending.status {
+                                            div {
+                                                class: "flex items-center gap-2 text-xs text-red-600",
+                                                "Failed to send: {e}"
+                                                button {
+                                                    class: "underline hover:text-red-800",
+                                                    onclick: move |_| {
+                                                        ws.retry_message(pending.id);
+                                                    },
+                                                    "Retry"
+                                                }
+                                                button {
+                                                    class: "underline hover:text-red-800",
+                                                    onclick: move |_| {
+                                                        outbox::discard(pending.id);
+                                                    },
+                                                    "Discard"
+                                                }
+                                            }
+                                        } else if matches!(pending.status, OutboxStatus::Sending | OutboxStatus::Unconfirmed) {
+                                            div {
+                                                class: "text-xs text-gray-400",
+                                                "Sending..."
+                                            }
+                                        } else {
+                                            div {
+                                                class: "text-xs text-gray-400",
+                                                "Waiting for connection..."
+                                            }
+                                        }
+                                    } else if let Some((_, content)) = editing.clone().filter(|(id, _)| *id == message.id) {
+                                        form {
+                                            class: "flex gap-2",
+                                            onsubmit: move |_| {
+                                                let content = editing_signal().map(|(_, x)| x).unwrap_or_default();
+                                                editing_signal.set(None);
+
+                                                let edit = MessageEdit {
+                                                    chat_id: message.chat_id,
+                                                    message_id: message.id,
+                                                    content: content.trim().to_string(),
+                                                };
+
+                                                if edit.content.is_empty() || edit.content == message.content {
+                                                    return;
+                                                }
+
+                                                spawn(async move {
+                                                    if let Err(e) = ws.edit_message(edit).await {
+                                                        message_error_signal.set(Some((message.id, format!("Failed to edit: {}", e))));
+                                                    }
+                                                });
+                                            },
+                                            input {
+                                                class: "flex-1 border rounded px-2 py-1 focus:outline-none focus:ring",
+                                                value: "{content}",
+                                                autofocus: true,
+                                                oninput: move |evt| {
+                                                    editing_signal.set(Some((message.id, evt.value())));
+                                                },
+                                                onkeydown: move |evt| {
+                                                    if evt.key() == Key::Escape {
+                                                        editing_signal.set(None);
+                                                    }
+                                                }
+                                            }
+                                            button {
+                                                class: "text-sm text-blue-600 hover:underline",
+                                                r#type: "submit",
+                                                "Save"
+                                            }
+                                            button {
+                                                class: "text-sm text-gray-500 hover:underline",
+                                                r#type: "button",
+                                                onclick: move |_| {
+                                                    editing_signal.set(None);
+                                                },
+                                                "Cancel"
+                                            }
+                                        }
+                                    } else {
+                                        components::markdown::Markdown {
+                                            content: message.content.clone()
+                                        }
+                                        components::reactions::Reactions {
+                                            chat_id: chat.id,
+                                            message_id: message.id
+                                        }
+                                        if message_states.get(&message.id) == Some(&MessageState::Edited) {
+                                            span {
+                                                class: "text-xs text-gray-400",
+                                                "(edited)"
+                                            }
+                                        }
+                                        if let Some(count) = reply_counts.get(&message.id) {
+                                            button {
+                                                class: "block text-xs text-blue-600 hover:underline",
+                                                onclick: move |_| {
+                                                    thread_signal.set(Some(message.id));
+                                                },
+                                                if *count == 1 { "1 reply" } else { "{count} replies" }
+                                            }
+                                        }
+                                        div {
+                                            class: "hidden group-hover:flex gap-2 text-xs text-gray-500",
+                                            button {
+                                                class: "hover:underline",
+                                                onclick: move |_| {
+                                                    replying_signal.set(Some(message.id));
+
+                                                    let _ = document::eval(r#"document.getElementById("message").focus()"#);
+                                                },
+                                                "Reply"
+                                            }
+                                            if user.as_ref().is_some_and(|x| message.creator == Some(x.id)) {
+                                                button {
+                                                    class: "hover:underline",
+                                                    onclick: move |_| {
+                                                        editing_signal.set(Some((message.id, message.content.clone())));
+                                                    },
+                                                    "Edit"
+                                                }
+                                                button {
+                                                    class: "hover:underline hover:text-red-600",
+                                                    onclick: move |_| {
+                                                        let delete = MessageDelete {
+                                                            chat_id: message.chat_id,
+                                                            message_id: message.id,
+                                                        };
+
+                                                        spawn(async move {
+                                                            if let Err(e) = ws.delete_message(delete).await {
+                                                                message_error_signal.set(Some((message.id, format!("Failed to delete: {}", e))));
+                                                            }
+                                                        });
+                                                    },
+                                                    "Delete"
+                                                }
+                                            }
+                                        }
+                                    }
+                                    if let Some((_, e)) = message_error.clone().filter(|(id, _)| *id == message.id) {
+                                        div {
+                                            class: "text-xs text-red-600",
+                                            "{e}"
+                                        }
+                                    }
+                                    if let Some(readers) = read_positions.get(&message.id) {
+                                        components::receipts::ReadReceipts {
+                                            readers: readers.clone(),
+                                            newest: Some(message.id) == newest_message_id
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    components::uploads::Uploads {
+                        chat_id: chat.id
+                    }
+                    if let Some(parent_id) = replying {
+                        div {
+                            class: "flex items-center gap-2 px-4 pt-2 border-t bg-white text-sm",
+                            span {
+                                class: "text-gray-500 flex-shrink-0",
+                                "Replying to"
+                            }
+                            div {
+                                class: "min-w-0 flex-1",
+                                components::thread::ReplyQuote {
+                                    chat_id: chat.id,
+                                    parent_id,
+                                    onclick: move |_| {
+                                        thread_signal.set(Some(parent_id));
                                     }
                                 }
                             }
+                            button {
+                                class: "px-2 py-1 border rounded text-xs hover:bg-gray-50",
+                                onclick: move |_| {
+                                    replying_signal.set(None);
+                                },
+                                "×"
+                            }
                         }
                     }
+                    components::typing::TypingIndicator {
+                        chat_id: chat.id
+                    }
                     form {
                         class: "flex gap-2 p-4 border-t bg-white",
+                        id: "message-form",
+                        // read by the upload script when a file comes in
+                        "data-chat-id": "{chat.id}",
                         onsubmit: move |_| {
                             async move {
                                 // get input value
@@ -468,10 +718,19 @@ pub fn Home() -> Element {
                                 let current_message = eval.recv::<String>().await.unwrap();
 
                                 if current_message != "" {
-                                    let _ =  ws_request(WebsocketClientMessageData::NewMessage(CreateRequest {
+                                    let request = CreateRequest {
                                         chat_id: selected_chat_id.unwrap(),
                                         content: current_message
-                                    })).await;
+                                    };
+
+                                    match replying_signal() {
+                                        Some(parent_id) => ws.reply(request, parent_id),
+                                        None => ws.new_message(request),
+                                    };
+
+                                    typing_notifier.stop();
+
+                                    replying_signal.set(None);
 
                                     update_height_signal.set(UpdateHeight::GoDown);
 
@@ -487,10 +746,39 @@ pub fn Home() -> Element {
                                 }
                             }
                         },
+                        // picked files are handled by js/upload.js
+                        label {
+                            class: "flex items-center px-3 border rounded cursor-pointer text-gray-500 hover:bg-gray-50",
+                            title: "Attach files",
+                            r#for: "attachment-input",
+                            svg {
+                                class: "w-5 h-5",
+                                fill: "none",
+                                stroke: "currentColor",
+                                view_box: "0 0 24 24",
+                                path {
+                                    stroke_linecap: "round",
+                                    stroke_linejoin: "round",
+                                    stroke_width: 2,
+                                    d: "M15.172 7l-6.586 6.586a2 2 0 102.828 2.828l6.414-6.586a4 4 0 00-5.656-5.656l-6.415 6.585a6 6 0 108.486 8.486L20.5 13"
+                                }
+                            }
+                        }
+                        input {
+                            r#type: "file",
+                            id: "attachment-input",
+                            multiple: true,
+                            class: "hidden"
+                        }
                         input {
                             class: "flex-1 border rounded px-3 py-2 focus:outline-none focus:ring",
-                            placeholder: "Type a message...",
+                            placeholder: "Type a message or drop files...",
                             id: "message",
+                            oninput: move |_| {
+                                if let Some(chat_id) = selected_chat_id {
+                                    typing_notifier.typed(chat_id);
+                                }
+                            },
                         },
                         button {
                             class: "bg-blue-600 text-white px-4 py-2 rounded hover:bg-blue-700",
@@ -500,6 +788,15 @@ pub fn Home() -> Element {
                     }
                 }
 
+                if let Some(message_id) = thread {
+                    components::thread::ThreadPanel {
+                        chat_id: chat.id,
+                        message_id,
+                        thread_signal,
+                        replying_signal
+                    }
+                }
+
                 aside {
                     // translate logic here
                     class: "w-64 bg-white border-l flex-col transition-transform duration-200 ease-in-out hidden lg:flex",
@@ -523,6 +820,7 @@ pub fn Home() -> Element {
                                     src: Some(user.profile_image.clone()),
                                     alt: user.display_name.clone(),
                                     size: components::avatar::Size::Small,
+                                    status: presence.get(&user.id).copied(),
                                 }
                                 {user.display_name.clone()}
                             }
@@ -561,6 +859,7 @@ pub fn Home() -> Element {
                                             src: Some(user.profile_image.clone()),
                                             alt: user.display_name.clone(),
                                             size: components::avatar::Size::Small,
+                                            status: presence.get(&user.id).copied(),
                                         },
                                         {user.display_name.clone()}
                                     }
@@ -571,5 +870,12 @@ pub fn Home() -> Element {
                 }
             }
         }
+        if let Some((chat_id, name)) = chat_settings {
+            components::chat_settings::ChatSettingsPanel {
+                chat_id,
+                name,
+                onclose: move |_| show_chat_settings_signal.set(false)
+            }
+        }
     }
 }
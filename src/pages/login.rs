@@ -1,9 +1,7 @@
-use anyhow::{anyhow, Context};
 use dioxus::prelude::*;
-use jsonwebtoken::DecodingKey;
-use shared::api::user::{AuthResponse, Claims, LoginRequest};
+use shared::api::user::{AuthResponse, LoginRequest};
 
-use crate::{components::navbar::Auth, route::Route, BACKEND_URL, CLAIMS, USER};
+use crate::{auth, config::use_config, route::Route, CLAIMS, USER};
 
 pub fn Login() -> Element {
     let mut email_signal = use_signal(|| "".to_string());
@@ -15,6 +13,7 @@ pub fn Login() -> Element {
     let error = error_signal();
 
     let navigator = use_navigator();
+    let config = use_config();
 
     use_effect(move || {
         let is_logged_in = CLAIMS().zip(USER()).is_some();
@@ -36,12 +35,12 @@ pub fn Login() -> Element {
                 form {
                     class: "space-y-4",
                     onsubmit: move |_| {
-                        to_owned![email, password];
+                        to_owned![email, password, config];
 
                         spawn(async move {
                             let task: Result<(), anyhow::Error> = async move {
                                 let client = reqwest::Client::new();
-                                let res = client.post(format!("{}/user/login", BACKEND_URL))
+                                let res = client.post(format!("{}/user/login", config.backend_url))
                                     .json(&LoginRequest {
                                         email,
                                         password,
@@ -52,24 +51,7 @@ pub fn Login() -> Element {
                                     .json::<AuthResponse>()
                                     .await?;
 
-                                let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
-                                validation.insecure_disable_signature_validation();
-
-                                let key = DecodingKey::from_secret(&[]);
-                                let payload = jsonwebtoken::decode::<Claims>(res.token.as_str(), &key, &validation)?;
-
-                                *CLAIMS.write() = Some(Auth {
-                                    claims: payload.claims,
-                                    token: res.token.clone(),
-                                });
-
-                                web_sys::window()
-                                    .context("failed to get window")?
-                                    .local_storage()
-                                    .map_err(|_| anyhow!("failed to get local storage"))?
-                                    .context("failed to get storage")?
-                                    .set_item("jwt_token", res.token.as_str())
-                                    .map_err(|_| anyhow!("failed to get local storage"))?;
+                                auth::login(res.token).await?;
 
                                 navigator.replace(Route::Home);
 
@@ -1,33 +1,20 @@
-use crate::{route::Route, BACKEND_URL, CLAIMS, USER};
-use anyhow::{anyhow, Context};
-use base64::{engine::general_purpose, Engine as _};
-use dioxus::{document::eval, prelude::*};
-use reqwest::{
-    header::{HeaderMap, HeaderName, HeaderValue},
-    multipart,
-};
-use shared::api::{
-    media::UploadFileResponse,
-    user::UpdateRequest,
-    websocket::{WebsocketClientMessageData, WebsocketServerResData},
+use crate::{
+    auth,
+    components::avatar_editor::AvatarEditor,
+    config::use_config,
+    route::Route,
+    upload::{self, AvatarSource},
+    ws::use_ws,
+    CLAIMS, USER,
 };
-use tokio::sync::oneshot;
+use dioxus::{document::eval, prelude::*};
+use shared::api::user::UpdateRequest;
 
 pub fn Profile() -> Element {
     let user = USER();
     let navigator = use_navigator();
-    let ws_channel = use_coroutine_handle::<(
-        WebsocketClientMessageData,
-        oneshot::Sender<Result<WebsocketServerResData, String>>,
-    )>();
-
-    let ws_request = move |req| -> oneshot::Receiver<_> {
-        let (tx, rx) = oneshot::channel();
-
-        ws_channel.send((req, tx));
-
-        rx
-    };
+    let config = use_config();
+    let ws = use_ws();
 
     use_effect(move || {
         if CLAIMS().is_none() {
@@ -35,8 +22,8 @@ pub fn Profile() -> Element {
         }
     });
 
-    let (user, token) = match user.zip(CLAIMS()) {
-        Some((u, a)) => (u, a.token),
+    let user = match user.filter(|_| CLAIMS().is_some()) {
+        Some(u) => u,
         None => return rsx! {},
     };
 
@@ -44,15 +31,20 @@ pub fn Profile() -> Element {
     let mut is_loading_signal = use_signal(|| false);
 
     let mut display_name_signal = use_signal(|| user.display_name);
-    let mut profile_image_signal = use_signal(|| (user.profile_image, false, String::new()));
+    // image shown and whether it is a new one waiting for upload
+    let mut profile_image_signal = use_signal(|| (user.profile_image, false));
+    // picked image while it is being cropped
+    let mut avatar_source_signal = use_signal::<Option<AvatarSource>>(|| None);
 
     let message = message_signal();
     let is_loading = is_loading_signal();
 
     let display_name = display_name_signal();
     let display_name_m = display_name.clone();
-    let (profile_image, profile_image_changed, file_name) = profile_image_signal();
-    let profile_image_m = profile_image.clone();
+    let (profile_image, profile_image_changed) = profile_image_signal();
+    let avatar_source = avatar_source_signal();
+    let preview = profile_image_changed.then(|| profile_image.clone());
+    let config_m = config.clone();
 
     rsx! {
         div {
@@ -83,7 +75,7 @@ pub fn Profile() -> Element {
                     class: "bg-white rounded-lg shadow-md p-6",
                     form {
                         onsubmit: move |_| {
-                            to_owned![display_name_m, profile_image_m, token, file_name];
+                            to_owned![display_name_m, config];
 
                             is_loading_signal.set(true);
                             message_signal.set(None);
@@ -96,38 +88,16 @@ pub fn Profile() -> Element {
                                     };
 
                                     if profile_image_changed {
-                                        let data_o = profile_image_m.strip_prefix("data:image;base64,");
-
-                                        let data = data_o.map(|x| general_purpose::STANDARD.decode(x))
-                                            .ok_or(anyhow!("failed to get data"))??;
-
-                                        let file_part = multipart::Part::bytes(data).file_name(file_name);
+                                        let res = upload::upload_avatar(&config).await?;
 
-                                        let form = multipart::Form::new()
-                                            .part("file", file_part);
+                                        let url = format!("{}{}", config.backend_url, res.path);
 
-                                        let mut headers = HeaderMap::new();
-
-                                        headers.insert(HeaderName::from_static("authorization"), HeaderValue::from_str(format!("Bearer {}", token).as_str())?);
-
-                                        let client = reqwest::Client::new();
-                                        let res = client.post(format!("{}/media/upload", BACKEND_URL))
-                                            .multipart(form)
-                                            .headers(headers)
-                                            .send()
-                                            .await?
-                                            .error_for_status()?
-                                            .json::<UploadFileResponse>()
-                                            .await?;
-
-                                        let url = format!("{}{}", BACKEND_URL, res.path);
-
-                                        *profile_image_signal.write() = (url.clone(), false, String::new());
+                                        *profile_image_signal.write() = (url.clone(), false);
+                                        avatar_source_signal.set(None);
                                         request.profile_image = Some(url);
                                     }
 
-                                    ws_request(WebsocketClientMessageData::ProfileUpdate(request)).await?
-                                        .map_err(|e| anyhow!(e))?;
+                                    ws.profile_update(request).await?;
 
                                     Ok(())
                                 }.await;
@@ -210,17 +180,15 @@ pub fn Profile() -> Element {
                                         r#type: "file",
                                         accept: "image/*",
                                         id: "profile-image-input",
-                                        onchange: move |evt| {
+                                        onchange: move |_| {
                                             async move {
-                                                if let Some(file_engine) = &evt.files() {
-                                                    let files = file_engine.files();
-
-                                                    let data_o = file_engine.read_file(files[0].as_str()).await;
-
-                                                    if let Some(data) = data_o {
-                                                        let encoded = general_purpose::STANDARD.encode(data);
-
-                                                        *profile_image_signal.write() = (format!("data:image;base64,{}", encoded), true, files[0].clone());
+                                                match upload::load_avatar("profile-image-input").await {
+                                                    Ok(source) => {
+                                                        message_signal.set(None);
+                                                        avatar_source_signal.set(Some(source));
+                                                    }
+                                                    Err(e) => {
+                                                        message_signal.set(Some((e.to_string(), false)));
                                                     }
                                                 }
                                             }
@@ -229,6 +197,48 @@ pub fn Profile() -> Element {
                                     }
                                 }
                             }
+                            if let Some(source) = avatar_source {
+                                div {
+                                    class: "mt-6 space-y-4",
+                                    AvatarEditor {
+                                        key: "{source.url}",
+                                        source,
+                                        preview,
+                                        name: display_name.clone(),
+                                        oncrop: move |crop| {
+                                            to_owned![config_m];
+
+                                            spawn(async move {
+                                                match upload::crop_avatar(&config_m, crop).await {
+                                                    Ok(Some(preview)) => {
+                                                        *profile_image_signal.write() = (preview, true);
+                                                    }
+                                                    // superseded by a later crop
+                                                    Ok(None) => {}
+                                                    Err(e) => {
+                                                        message_signal.set(Some((e.to_string(), false)));
+                                                    }
+                                                }
+                                            });
+                                        }
+                                    }
+                                    button {
+                                        r#type: "button",
+                                        onclick: move |_| {
+                                            let current = USER
+                                                .peek()
+                                                .as_ref()
+                                                .map(|x| x.profile_image.clone())
+                                                .unwrap_or_default();
+
+                                            avatar_source_signal.set(None);
+                                            *profile_image_signal.write() = (current, false);
+                                        },
+                                        class: "text-sm text-gray-600 hover:text-gray-800",
+                                        "Cancel"
+                                    }
+                                }
+                            }
                         }
 
                         div {
@@ -279,29 +289,9 @@ pub fn Profile() -> Element {
                             button {
                                 r#type: "button",
                                 onclick: move |_| {
-                                    *CLAIMS.write() = None;
-                                    *USER.write() = None;
-
-                                    let task = move || -> Result<(), anyhow::Error> {
-                                        web_sys::window()
-                                            .context("failed to get window")?
-                                            .local_storage()
-                                            .map_err(|_| anyhow!("failed to get local storage"))?
-                                            .context("failed to get storage")?
-                                            .remove_item("jwt_token")
-                                            .map_err(|_| anyhow!("failed to get local storage"))?;
-
-                                        Ok(())
-                                    };
+                                    auth::logout();
 
-                                    match task() {
-                                        Ok(_) => {
-                                            navigator.replace(Route::Login);
-                                        },
-                                        Err(e) => {
-                                            message_signal.write().replace((e.to_string(), false));
-                                        }
-                                    }
+                                    navigator.replace(Route::Login);
                                 },
                                 class: "px-6 py-2 border border-red-300 text-red-700 rounded-md hover:bg-red-50 transition-colors",
                                 "Logout"
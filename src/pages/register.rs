@@ -1,9 +1,7 @@
-use anyhow::{anyhow, Context};
 use dioxus::prelude::*;
-use jsonwebtoken::DecodingKey;
-use shared::api::user::{AuthResponse, Claims, RegisterRequest};
+use shared::api::user::{AuthResponse, RegisterRequest};
 
-use crate::{components::navbar::Auth, route::Route, BACKEND_URL, CLAIMS, USER};
+use crate::{auth, config::use_config, route::Route, CLAIMS, USER};
 
 pub fn Register() -> Element {
     let mut email_signal = use_signal(|| "".to_string());
@@ -17,6 +15,7 @@ pub fn Register() -> Element {
     let error = error_signal();
 
     let navigator = use_navigator();
+    let config = use_config();
 
     use_effect(move || {
         let is_logged_in = CLAIMS().zip(USER()).is_some();
@@ -38,12 +37,12 @@ pub fn Register() -> Element {
                 form {
                     class: "space-y-4",
                     onsubmit: move |_| {
-                        to_owned![display_name, email, password];
+                        to_owned![display_name, email, password, config];
 
                         spawn(async move {
                             let task: anyhow::Result<()> = async move {
                                 let client = reqwest::Client::new();
-                                let res = client.post(format!("{}/user/register", BACKEND_URL))
+                                let res = client.post(format!("{}/user/register", config.backend_url))
                                     .json(&RegisterRequest{
                                         display_name,
                                         email,
@@ -55,24 +54,7 @@ pub fn Register() -> Element {
                                     .json::<AuthResponse>()
                                     .await?;
 
-                                let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
-                                validation.insecure_disable_signature_validation();
-
-                                let key = DecodingKey::from_secret(&[]);
-                                let payload = jsonwebtoken::decode::<Claims>(res.token.as_str(), &key, &validation).unwrap();
-
-                                *CLAIMS.write() = Some(Auth {
-                                    claims: payload.claims,
-                                    token: res.token.clone(),
-                                });
-
-                                web_sys::window()
-                                    .context("failed to get window")?
-                                    .local_storage()
-                                    .map_err(|_| anyhow!("failed to get local storage"))?
-                                    .context("failed to get local storage")?
-                                    .set_item("jwt_token", res.token.as_str())
-                                    .map_err(|_| anyhow!("failed to get local storage"))?;
+                                auth::login(res.token).await?;
 
                                 navigator.replace(Route::Home);
 
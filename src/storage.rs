@@ -0,0 +1,242 @@
+use std::cell::RefCell;
+
+use bson::oid::ObjectId;
+use dioxus::prelude::*;
+use dioxus_logger::tracing::warn;
+use js_sys::{Array, Promise};
+use serde::{Deserialize, Serialize};
+use shared::models::chat::ChatSafe;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{IdbDatabase, IdbOpenDbRequest, IdbRequest, IdbTransactionMode};
+
+use crate::{
+    ws::{outbox, sync::MessageState},
+    CHATS, CLAIMS, HYDRATED, MESSAGE_STATES,
+};
+
+const DB_VERSION: u32 = 1;
+const CHATS_STORE: &str = "chats";
+/// Newest messages kept per chat, older pages are evicted
+const MAX_CACHED_MESSAGES: usize = 100;
+/// Only the most recently active chats keep their history in the cache
+const MAX_CHATS_WITH_HISTORY: usize = 20;
+
+/// A chat as cached, along with what is known about edits and deletes of
+/// its messages since the server doesn't send that with them
+#[derive(Serialize, Deserialize)]
+struct CachedChat {
+    #[serde(flatten)]
+    chat: ChatSafe,
+    #[serde(default)]
+    message_states: Vec<(ObjectId, MessageState)>,
+}
+
+thread_local! {
+    static DB: RefCell<Option<(ObjectId, IdbDatabase)>> = const { RefCell::new(None) };
+}
+
+async fn wait(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+
+    JsFuture::from(promise).await?;
+
+    request.result()
+}
+
+/// Opens the cache of `user_id`, every user gets their own database so a
+/// shared browser never shows someone else's chats
+async fn open(user_id: ObjectId) -> Result<IdbDatabase, JsValue> {
+    let cached = DB.with_borrow(|x| {
+        x.as_ref()
+            .filter(|(id, _)| *id == user_id)
+            .map(|(_, db)| db.clone())
+    });
+
+    if let Some(db) = cached {
+        return Ok(db);
+    }
+
+    let request = web_sys::window()
+        .ok_or("failed to get window")?
+        .indexed_db()?
+        .ok_or("indexed db not supported")?
+        .open_with_u32(&format!("wpp-cache-{}", user_id), DB_VERSION)?;
+
+    let upgrade = Closure::once(move |event: web_sys::Event| {
+        let db = event
+            .target()
+            .and_then(|x| x.dyn_into::<IdbOpenDbRequest>().ok())
+            .and_then(|x| x.result().ok())
+            .and_then(|x| x.dyn_into::<IdbDatabase>().ok());
+
+        if let Some(db) = db {
+            let _ = db.create_object_store(CHATS_STORE);
+        }
+    });
+
+    request.set_onupgradeneeded(Some(upgrade.as_ref().unchecked_ref()));
+
+    let db = wait(&request).await?.dyn_into::<IdbDatabase>()?;
+
+    DB.set(Some((user_id, db.clone())));
+
+    Ok(db)
+}
+
+fn user_id() -> Option<ObjectId> {
+    CLAIMS.peek().as_ref().map(|x| x.claims.user_id)
+}
+
+/// Serializes the chat, dropping messages that don't fit the cache
+fn record(chat: &ChatSafe, keep_history: bool) -> Option<(String, String)> {
+    let mut chat = chat.clone();
+
+    // local echoes are replaced by the stored message once it is sent
+    chat.messages.retain(|x| !outbox::is_echo(x.id));
+
+    let evicted = match keep_history {
+        true => chat.messages.len().saturating_sub(MAX_CACHED_MESSAGES),
+        false => chat.messages.len(),
+    };
+    chat.messages.drain(..evicted);
+
+    let message_states = {
+        let states = MESSAGE_STATES.peek();
+
+        chat.messages
+            .iter()
+            .filter_map(|x| states.get(&x.id).map(|state| (x.id, *state)))
+            .collect()
+    };
+
+    let key = chat.id.to_hex();
+    let value = serde_json::to_string(&CachedChat {
+        chat,
+        message_states,
+    })
+    .ok()?;
+
+    Some((key, value))
+}
+
+async fn write(
+    user_id: ObjectId,
+    records: Vec<(String, String)>,
+    clear: bool,
+) -> Result<(), JsValue> {
+    let db = open(user_id).await?;
+    let transaction =
+        db.transaction_with_str_and_mode(CHATS_STORE, IdbTransactionMode::Readwrite)?;
+    let store = transaction.object_store(CHATS_STORE)?;
+
+    if clear {
+        store.clear()?;
+    }
+
+    for (key, value) in records {
+        store.put_with_key(&JsValue::from_str(&value), &JsValue::from_str(&key))?;
+    }
+
+    Ok(())
+}
+
+fn spawn_write(records: Vec<(String, String)>, clear: bool) {
+    let user_id = match user_id() {
+        Some(x) => x,
+        None => return,
+    };
+
+    spawn_local(async move {
+        if let Err(e) = write(user_id, records, clear).await {
+            warn!("failed to update cache {:?}", e);
+        }
+    });
+}
+
+/// Stores one chat of `chats` after it changed
+pub fn save_chat(chats: &[ChatSafe], chat_id: ObjectId) {
+    let record = chats
+        .iter()
+        .enumerate()
+        .find(|(_, x)| x.id == chat_id)
+        .and_then(|(i, chat)| record(chat, i < MAX_CHATS_WITH_HISTORY));
+
+    if let Some(record) = record {
+        spawn_write(vec![record], false);
+    }
+}
+
+/// Replaces the whole cache with `chats`
+pub fn save_chats(chats: &[ChatSafe]) {
+    let records = chats
+        .iter()
+        .enumerate()
+        .filter_map(|(i, chat)| record(chat, i < MAX_CHATS_WITH_HISTORY))
+        .collect();
+
+    spawn_write(records, true);
+}
+
+pub fn clear() {
+    spawn_write(Vec::new(), true);
+}
+
+async fn read(user_id: ObjectId) -> Result<Vec<CachedChat>, JsValue> {
+    let db = open(user_id).await?;
+    let transaction = db.transaction_with_str(CHATS_STORE)?;
+    let store = transaction.object_store(CHATS_STORE)?;
+
+    let values = wait(&store.get_all()?).await?.dyn_into::<Array>()?;
+
+    let mut chats = values
+        .iter()
+        .filter_map(|x| x.as_string())
+        .filter_map(|x| serde_json::from_str::<CachedChat>(&x).ok())
+        .collect::<Vec<_>>();
+
+    chats.sort_by(|a, b| {
+        a.chat
+            .last_message_ts
+            .cmp(&b.chat.last_message_ts)
+            .reverse()
+    });
+
+    Ok(chats)
+}
+
+/// Fills `CHATS` from the cache of `user_id` unless the socket got there
+/// first or someone else logged in meanwhile, marks the cache as loaded even
+/// if it couldn't be read
+pub async fn hydrate(user_id: ObjectId) {
+    match read(user_id).await {
+        Ok(cached) if self::user_id() == Some(user_id) => {
+            let (chats, states) = cached
+                .into_iter()
+                .map(|x| (x.chat, x.message_states))
+                .unzip::<_, _, Vec<_>, Vec<_>>();
+
+            // states seen live are newer
+            let message_states = &mut (*MESSAGE_STATES.write());
+
+            for (message_id, state) in states.into_iter().flatten() {
+                message_states.entry(message_id).or_insert(state);
+            }
+
+            let current = &mut (*CHATS.write());
+
+            if current.is_empty() {
+                *current = chats;
+            }
+        }
+        Ok(_) => {}
+        Err(e) => warn!("failed to read cache {:?}", e),
+    }
+
+    if self::user_id() == Some(user_id) {
+        *HYDRATED.write() = Some(user_id);
+    }
+}
@@ -0,0 +1,80 @@
+//! Unread state of chats, derived from the current user's read mark
+
+use bson::oid::ObjectId;
+use shared::models::chat::ChatSafe;
+
+use crate::notifications::{ChatSettings, Mode, Settings};
+
+/// Title of the page without unread messages
+pub const TITLE: &str = "CHET";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unread {
+    /// unread messages that are loaded
+    pub count: usize,
+    /// older unread messages may not be loaded
+    pub more: bool,
+}
+
+impl Unread {
+    /// For totals, a chat with only unloaded unread messages counts once
+    pub fn at_least(&self) -> usize {
+        self.count.max(1)
+    }
+}
+
+/// `None` when `user_id` has seen everything in `chat` or `settings` say
+/// the rest isn't worth a badge
+pub fn unread(chat: &ChatSafe, user_id: ObjectId, settings: &ChatSettings) -> Option<Unread> {
+    let me = chat.users.iter().find(|x| x.id == user_id)?;
+    let seen = me.last_message_seen_ts;
+
+    if chat.last_message_ts <= seen || settings.muted() {
+        return None;
+    }
+
+    // our own messages are never unread
+    let newer = chat
+        .messages
+        .iter()
+        .filter(|x| x.created_at > seen && x.creator != Some(user_id));
+
+    // mentions in unloaded messages can't be known, only loaded ones count
+    if settings.mode == Mode::Mentions {
+        let count = newer
+            .filter(|x| settings.wants(&x.content, &me.display_name))
+            .count();
+
+        return (count > 0).then_some(Unread { count, more: false });
+    }
+
+    let count = newer.count();
+    let latest_own = chat
+        .messages
+        .last()
+        .is_some_and(|x| x.creator == Some(user_id) && x.created_at >= chat.last_message_ts);
+
+    // the gap between the read mark and the oldest loaded message
+    let more = !latest_own && chat.messages.first().is_none_or(|x| x.created_at > seen);
+
+    match count > 0 || more {
+        true => Some(Unread { count, more }),
+        false => None,
+    }
+}
+
+/// Unread messages across `chats`, for the page title
+pub fn total(chats: &[ChatSafe], user_id: ObjectId, settings: &Settings) -> usize {
+    chats
+        .iter()
+        .filter_map(|x| unread(x, user_id, &settings.chat(x.id)))
+        .map(|x| x.at_least())
+        .sum()
+}
+
+pub fn title(total: usize) -> String {
+    match total {
+        0 => TITLE.to_string(),
+        total => format!("({}) {}", total, TITLE),
+    }
+}
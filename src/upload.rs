@@ -0,0 +1,342 @@
+//! Uploads to the media endpoint, files are processed and sent by
+//! `js/media.js` so images can be compressed and progress reported. Chat
+//! attachments are driven by `js/upload.js`, each finished upload is sent as a
+//! message linking to it
+
+use anyhow::anyhow;
+use bson::oid::ObjectId;
+use dioxus::prelude::*;
+use dioxus_logger::tracing::warn;
+use serde::{Deserialize, Serialize};
+use shared::api::{media::UploadFileResponse, message::CreateRequest};
+
+use crate::{
+    auth,
+    config::{use_config, Config},
+    ws::use_ws,
+    UPLOADS,
+};
+
+/// Side of uploaded avatars, enough for every avatar size on hidpi screens
+pub const AVATAR_SIZE: u32 = 256;
+
+const LOAD_AVATAR_JS: &str = r#"
+    const input_id = await dioxus.recv();
+
+    try {
+        const file = document.getElementById(input_id)?.files?.[0];
+
+        if (!file?.type.startsWith("image/")) {
+            throw new Error("Please choose an image");
+        }
+
+        const bitmap = await createImageBitmap(file);
+        const source = {
+            url: URL.createObjectURL(file),
+            width: bitmap.width,
+            height: bitmap.height,
+        };
+        bitmap.close();
+
+        if (window.wppAvatar) {
+            URL.revokeObjectURL(window.wppAvatar.source.url);
+            URL.revokeObjectURL(window.wppAvatar.preview);
+        }
+
+        window.wppAvatar = { original: file, source };
+
+        dioxus.send({ Ok: source });
+    } catch (e) {
+        dioxus.send({ Err: e.message });
+    }
+"#;
+
+const CROP_AVATAR_JS: &str = concat!(
+    include_str!("../js/media.js"),
+    r#"
+    const { crop, ...options } = await dioxus.recv();
+
+    try {
+        const avatar = window.wppAvatar;
+
+        if (!avatar) {
+            throw new Error("No image chosen");
+        }
+
+        // crops can finish out of order, only the newest one is kept
+        const seq = (avatar.crops = (avatar.crops ?? 0) + 1);
+
+        const file = await prepareImage(avatar.original, options, { crop });
+
+        if (avatar !== window.wppAvatar || seq !== avatar.crops) {
+            dioxus.send({ Ok: null });
+        } else {
+            URL.revokeObjectURL(avatar.preview);
+
+            avatar.file = file;
+            avatar.preview = URL.createObjectURL(file);
+
+            dioxus.send({ Ok: avatar.preview });
+        }
+    } catch (e) {
+        dioxus.send({ Err: e.message });
+    }
+    "#
+);
+
+const UPLOAD_AVATAR_JS: &str = concat!(
+    include_str!("../js/media.js"),
+    r#"
+    const target = await dioxus.recv();
+
+    try {
+        if (!window.wppAvatar?.file) {
+            throw new Error("No image cropped");
+        }
+
+        dioxus.send({ Ok: await uploadFile(target, window.wppAvatar.file) });
+    } catch (e) {
+        dioxus.send({ Err: e.message });
+    }
+    "#
+);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadStatus {
+    Uploading { loaded: f64, total: f64 },
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upload {
+    pub id: u32,
+    /// chat that was open when the file was picked
+    pub chat_id: ObjectId,
+    pub name: String,
+    pub mime: String,
+    pub status: UploadStatus,
+}
+
+/// What the scripts need to process and send files
+#[derive(Serialize)]
+struct UploadConfig<'a> {
+    backend_url: &'a str,
+    token_key: &'a str,
+    max_dimension: u32,
+    quality: f64,
+}
+
+impl<'a> UploadConfig<'a> {
+    fn new(config: &'a Config) -> Self {
+        Self {
+            backend_url: &config.backend_url,
+            token_key: auth::TOKEN_KEY,
+            max_dimension: config.image_max_dimension,
+            quality: config.image_quality,
+        }
+    }
+}
+
+/// Image picked for an avatar, `url` is an object url of the original
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AvatarSource {
+    pub url: String,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Square region of an image, in source pixels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Crop {
+    pub x: f64,
+    pub y: f64,
+    pub size: f64,
+}
+
+#[derive(Serialize)]
+struct CropConfig<'a> {
+    crop: Crop,
+    #[serde(flatten)]
+    upload: UploadConfig<'a>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "t")]
+enum UploadEvent {
+    /// sent before the file is compressed, `chat_id` is the chat of the
+    /// form it was picked in
+    Started {
+        id: u32,
+        chat_id: String,
+        name: String,
+        mime: String,
+        size: f64,
+    },
+    Progress {
+        id: u32,
+        loaded: f64,
+        total: f64,
+    },
+    /// `name` and `mime` of the file as uploaded, compression may change them
+    Done {
+        id: u32,
+        chat_id: String,
+        path: String,
+        name: String,
+        mime: String,
+    },
+    Failed {
+        id: u32,
+        error: String,
+    },
+}
+
+/// Message content for an uploaded file, images are embedded
+fn attachment_content(name: &str, mime: &str, url: &str) -> String {
+    let name = name.replace(['[', ']', '\n', '\r'], "_");
+    let name = match name.trim() {
+        "" => "file",
+        name => name,
+    };
+
+    // the markdown url ends at the first `)` or whitespace
+    let url = url
+        .replace(')', "%29")
+        .replace(' ', "%20")
+        .replace(['\n', '\r', '\t'], "");
+
+    match mime.starts_with("image/") {
+        true => format!("![{}]({})", name, url),
+        false => format!("[{}]({})", name, url),
+    }
+}
+
+/// Reads the image picked in the `input_id` file input for `crop_avatar`
+pub async fn load_avatar(input_id: &str) -> anyhow::Result<AvatarSource> {
+    let mut eval = document::eval(LOAD_AVATAR_JS);
+
+    eval.send(input_id)?;
+
+    eval.recv::<Result<AvatarSource, String>>()
+        .await?
+        .map_err(|e| anyhow!(e))
+}
+
+/// Cuts `crop` out of the loaded image and scales it to `AVATAR_SIZE`, the
+/// result is kept for `upload_avatar` and an object url of it is returned
+/// for previews. `None` if a later crop was started meanwhile
+pub async fn crop_avatar(config: &Config, crop: Crop) -> anyhow::Result<Option<String>> {
+    let mut eval = document::eval(CROP_AVATAR_JS);
+
+    eval.send(CropConfig {
+        crop,
+        upload: UploadConfig {
+            max_dimension: AVATAR_SIZE,
+            ..UploadConfig::new(config)
+        },
+    })?;
+
+    eval.recv::<Result<Option<String>, String>>()
+        .await?
+        .map_err(|e| anyhow!(e))
+}
+
+/// Uploads the image from the last `crop_avatar`
+pub async fn upload_avatar(config: &Config) -> anyhow::Result<UploadFileResponse> {
+    let mut eval = document::eval(UPLOAD_AVATAR_JS);
+
+    eval.send(UploadConfig::new(config))?;
+
+    eval.recv::<Result<UploadFileResponse, String>>()
+        .await?
+        .map_err(|e| anyhow!(e))
+}
+
+/// Uploads files picked with `#attachment-input`, dropped on `#message-form`
+/// or pasted into `#message` into the chat of the form, tracked in `UPLOADS`.
+/// Started in `Root` so uploads finish while the chat isn't shown
+pub fn use_uploads() {
+    let ws = use_ws();
+    let config = use_config();
+
+    use_future(move || {
+        let config = config.clone();
+
+        async move {
+            let mut eval = document::eval(concat!(
+                include_str!("../js/media.js"),
+                include_str!("../js/upload.js")
+            ));
+
+            let _ = eval.send(UploadConfig::new(&config));
+
+            loop {
+                let event = match eval.recv::<UploadEvent>().await {
+                    Ok(event) => event,
+                    Err(e) => {
+                        warn!("upload script stopped {:?}", e);
+
+                        break;
+                    }
+                };
+
+                let uploads = &mut (*UPLOADS.write());
+
+                match event {
+                    UploadEvent::Started {
+                        id,
+                        chat_id,
+                        name,
+                        mime,
+                        size,
+                    } => {
+                        if let Ok(chat_id) = ObjectId::parse_str(&chat_id) {
+                            uploads.push(Upload {
+                                id,
+                                chat_id,
+                                name,
+                                mime,
+                                status: UploadStatus::Uploading {
+                                    loaded: 0.0,
+                                    total: size,
+                                },
+                            });
+                        }
+                    }
+                    UploadEvent::Progress { id, loaded, total } => {
+                        if let Some(upload) = uploads.iter_mut().find(|x| x.id == id) {
+                            upload.status = UploadStatus::Uploading { loaded, total };
+                        }
+                    }
+                    UploadEvent::Done {
+                        id,
+                        chat_id,
+                        path,
+                        name,
+                        mime,
+                    } => {
+                        // untracked uploads were started before a logout
+                        let tracked = uploads.iter().any(|x| x.id == id);
+                        uploads.retain(|x| x.id != id);
+
+                        let chat_id = ObjectId::parse_str(&chat_id).ok().filter(|_| tracked);
+
+                        if let Some(chat_id) = chat_id {
+                            let url = format!("{}{}", config.backend_url, path);
+
+                            ws.new_message(CreateRequest {
+                                chat_id,
+                                content: attachment_content(&name, &mime, &url),
+                            });
+                        }
+                    }
+                    UploadEvent::Failed { id, error } => {
+                        if let Some(upload) = uploads.iter_mut().find(|x| x.id == id) {
+                            upload.status = UploadStatus::Failed(error);
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,830 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use dioxus::document::EvalError;
+use dioxus::prelude::*;
+use dioxus_logger::tracing::{self, info, warn};
+use futures_util::{SinkExt, StreamExt};
+use gloo_timers::future::TimeoutFuture;
+use shared::api::{
+    chat,
+    message::{CreateRequest, GetRequest},
+    user::UpdateRequest,
+    websocket::{
+        MediaSoupMessage, WebsocketClientMessage, WebsocketClientMessageData,
+        WebsocketServerMessage, WebsocketServerResData,
+    },
+};
+use shared::models::{chat::ChatSafe, message::Message};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+use ws_stream_wasm::WsMessage::Text;
+use {pharos::*, ws_stream_wasm::*};
+
+use crate::{
+    auth, config::Config, notifications, storage, CHATS, CLAIMS, CONNECTION_STATE, PRESENCE,
+    REACTIONS, TYPING, USER,
+};
+use ext::{
+    ChangedMessage, ExtClientMessage, ExtClientMessageData, ExtServerMessage, MessageDelete,
+    MessageEdit, MessageStatesRequest, PresenceEvent, PresenceRequest, Reaction, ReactionUpdate,
+    ReactionsRequest, ReplyLink, ReplyLinksRequest,
+};
+
+pub mod ext;
+mod handshake;
+pub mod outbox;
+pub mod presence;
+pub mod sync;
+pub mod typing;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WsError {
+    /// error returned by the server
+    Server(String),
+    /// server answered with a different response than requested
+    UnexpectedResponse,
+    /// socket task dropped the request without answering
+    Dropped,
+    /// no response before the request deadline
+    Timeout,
+    /// socket closed before the response arrived
+    ConnectionLost,
+    /// server rejected the token during the handshake
+    Unauthorized(String),
+}
+
+impl fmt::Display for WsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WsError::Server(e) => write!(f, "{}", e),
+            WsError::UnexpectedResponse => write!(f, "unexpected response"),
+            WsError::Dropped => write!(f, "request dropped"),
+            WsError::Timeout => write!(f, "request timed out"),
+            WsError::ConnectionLost => write!(f, "connection lost"),
+            WsError::Unauthorized(e) => write!(f, "unauthorized: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WsError {}
+
+pub type WsResponder = oneshot::Sender<Result<WebsocketServerResData, WsError>>;
+pub type ExtResponder = oneshot::Sender<Result<serde_json::Value, WsError>>;
+
+pub enum WsCommand {
+    Request(WebsocketClientMessageData, WsResponder),
+    /// request outside of the `shared` protocol, see `ext`
+    ExtRequest(ExtClientMessageData, ExtResponder),
+    /// like `ExtRequest` but nothing is answered, dropped while disconnected
+    ExtNotify(ExtClientMessageData),
+    /// skip the backoff wait and reconnect right away
+    Reconnect,
+    /// send queued outbox messages if connected
+    FlushOutbox,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Reconnecting {
+        attempt: u32,
+        next_retry_at: DateTime<Utc>,
+    },
+    /// no session or the browser has no network
+    Offline,
+}
+
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How often expired typing indicators are cleared
+const TYPING_SWEEP: Duration = Duration::from_secs(1);
+
+/// How often an idle session checks that its user is still logged in
+const SESSION_CHECK: Duration = Duration::from_secs(1);
+
+/// Handle to the socket coroutine started in `App`
+#[derive(Clone, Copy)]
+pub struct WsClient {
+    channel: Coroutine<WsCommand>,
+    timeout: Duration,
+}
+
+pub fn use_ws() -> WsClient {
+    WsClient {
+        channel: use_coroutine_handle::<WsCommand>(),
+        timeout: DEFAULT_TIMEOUT,
+    }
+}
+
+impl WsClient {
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    async fn response<T>(&self, rx: oneshot::Receiver<Result<T, WsError>>) -> Result<T, WsError> {
+        tokio::select! {
+            res = rx => res.map_err(|_| WsError::Dropped)?,
+            _ = TimeoutFuture::new(self.timeout.as_millis() as u32) => Err(WsError::Timeout),
+        }
+    }
+
+    /// Dropping the returned future cancels the request, the socket task
+    /// forgets its responder on the next sweep
+    pub async fn request(
+        &self,
+        data: WebsocketClientMessageData,
+    ) -> Result<WebsocketServerResData, WsError> {
+        let (tx, rx) = oneshot::channel();
+
+        self.channel.send(WsCommand::Request(data, tx));
+
+        self.response(rx).await
+    }
+
+    pub async fn ext_request(
+        &self,
+        data: ExtClientMessageData,
+    ) -> Result<serde_json::Value, WsError> {
+        let (tx, rx) = oneshot::channel();
+
+        self.channel.send(WsCommand::ExtRequest(data, tx));
+
+        self.response(rx).await
+    }
+
+    pub fn ext_notify(&self, data: ExtClientMessageData) {
+        self.channel.send(WsCommand::ExtNotify(data));
+    }
+
+    pub fn reconnect(&self) {
+        self.channel.send(WsCommand::Reconnect);
+    }
+
+    pub async fn get_chats(&self) -> Result<Vec<ChatSafe>, WsError> {
+        match self.request(WebsocketClientMessageData::GetChats).await? {
+            WebsocketServerResData::GetChats(chats) => Ok(chats),
+            _ => Err(WsError::UnexpectedResponse),
+        }
+    }
+
+    /// Also looks up which of the fetched messages are replies, their
+    /// reactions and edits, in the background so paging never waits on
+    /// extensions
+    pub async fn get_messages(&self, request: GetRequest) -> Result<Vec<Message>, WsError> {
+        let chat_id = request.chat_id;
+
+        let messages = match self
+            .request(WebsocketClientMessageData::GetMessages(request))
+            .await?
+        {
+            WebsocketServerResData::GetMessages(messages) => messages,
+            _ => return Err(WsError::UnexpectedResponse),
+        };
+
+        if !messages.is_empty() {
+            let message_ids = messages.iter().map(|x| x.id).collect::<Vec<_>>();
+            let ws = *self;
+
+            spawn(async move {
+                let (links, reactions, states) = futures_util::future::join3(
+                    ws.reply_links(chat_id, message_ids.clone()),
+                    ws.reactions(chat_id, message_ids.clone()),
+                    ws.message_states(chat_id, message_ids.clone()),
+                )
+                .await;
+
+                // a server without the extension just has nothing to add
+                let links = links.unwrap_or_else(|e| {
+                    warn!("failed to get reply links {}", e);
+
+                    Vec::new()
+                });
+                let reactions = reactions.unwrap_or_else(|e| {
+                    warn!("failed to get reactions {}", e);
+
+                    Vec::new()
+                });
+
+                let states = states.unwrap_or_else(|e| {
+                    warn!("failed to get message states {}", e);
+
+                    Vec::new()
+                });
+
+                sync::link_replies(links);
+                sync::set_reactions(&message_ids, reactions);
+                sync::set_message_states(chat_id, states);
+            });
+        }
+
+        Ok(messages)
+    }
+
+    pub async fn get_presence(
+        &self,
+        user_ids: Vec<ObjectId>,
+    ) -> Result<Vec<PresenceEvent>, WsError> {
+        let presence = self
+            .ext_request(ExtClientMessageData::GetPresence(PresenceRequest {
+                user_ids,
+            }))
+            .await?;
+
+        serde_json::from_value(presence).map_err(|_| WsError::UnexpectedResponse)
+    }
+
+    async fn reactions(
+        &self,
+        chat_id: ObjectId,
+        message_ids: Vec<ObjectId>,
+    ) -> Result<Vec<Reaction>, WsError> {
+        let reactions = self
+            .ext_request(ExtClientMessageData::GetReactions(ReactionsRequest {
+                chat_id,
+                message_ids,
+            }))
+            .await?;
+
+        serde_json::from_value(reactions).map_err(|_| WsError::UnexpectedResponse)
+    }
+
+    /// Adds or removes our `emoji` on the message, shown before the server
+    /// confirms and taken back if it fails
+    pub async fn toggle_reaction(&self, update: ReactionUpdate) -> Result<(), WsError> {
+        let user_id = match CLAIMS.peek().as_ref() {
+            Some(auth) => auth.claims.user_id,
+            None => return Err(WsError::ConnectionLost),
+        };
+
+        let reaction = Reaction {
+            chat_id: update.chat_id,
+            message_id: update.message_id,
+            user_id,
+            emoji: update.emoji.clone(),
+        };
+
+        let reacted = REACTIONS
+            .peek()
+            .get(&update.message_id)
+            .is_some_and(|x| x.contains(&reaction));
+
+        let res = if reacted {
+            sync::remove_reaction(&reaction);
+
+            self.ext_request(ExtClientMessageData::RemoveReaction(update))
+                .await
+        } else {
+            sync::add_reaction(reaction.clone());
+
+            self.ext_request(ExtClientMessageData::AddReaction(update))
+                .await
+        };
+
+        if let Err(e) = res {
+            match reacted {
+                true => sync::add_reaction(reaction),
+                false => sync::remove_reaction(&reaction),
+            }
+
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    async fn message_states(
+        &self,
+        chat_id: ObjectId,
+        message_ids: Vec<ObjectId>,
+    ) -> Result<Vec<ChangedMessage>, WsError> {
+        let states = self
+            .ext_request(ExtClientMessageData::GetMessageStates(
+                MessageStatesRequest {
+                    chat_id,
+                    message_ids,
+                },
+            ))
+            .await?;
+
+        serde_json::from_value(states).map_err(|_| WsError::UnexpectedResponse)
+    }
+
+    async fn reply_links(
+        &self,
+        chat_id: ObjectId,
+        message_ids: Vec<ObjectId>,
+    ) -> Result<Vec<ReplyLink>, WsError> {
+        let links = self
+            .ext_request(ExtClientMessageData::GetReplyLinks(ReplyLinksRequest {
+                chat_id,
+                message_ids,
+            }))
+            .await?;
+
+        serde_json::from_value(links).map_err(|_| WsError::UnexpectedResponse)
+    }
+
+    /// Queues the message in the outbox, it is shown right away as a local
+    /// echo and delivered once connected
+    pub fn new_message(&self, request: CreateRequest) -> Uuid {
+        self.enqueue(request, None)
+    }
+
+    pub fn reply(&self, request: CreateRequest, parent_id: ObjectId) -> Uuid {
+        self.enqueue(request, Some(parent_id))
+    }
+
+    fn enqueue(&self, request: CreateRequest, parent_id: Option<ObjectId>) -> Uuid {
+        sync::bump_chat(request.chat_id);
+
+        let id = outbox::enqueue(request.chat_id, request.content, parent_id);
+
+        self.flush_outbox();
+
+        id
+    }
+
+    fn flush_outbox(&self) {
+        self.channel.send(WsCommand::FlushOutbox);
+    }
+
+    pub fn retry_message(&self, id: Uuid) {
+        outbox::retry(id);
+
+        self.flush_outbox();
+    }
+
+    pub async fn edit_message(&self, edit: MessageEdit) -> Result<(), WsError> {
+        self.ext_request(ExtClientMessageData::EditMessage(edit.clone()))
+            .await?;
+
+        sync::edit_message(edit);
+
+        Ok(())
+    }
+
+    pub async fn delete_message(&self, delete: MessageDelete) -> Result<(), WsError> {
+        self.ext_request(ExtClientMessageData::DeleteMessage(delete.clone()))
+            .await?;
+
+        sync::delete_message(delete);
+
+        Ok(())
+    }
+
+    pub async fn create_chat(&self, request: chat::CreateRequest) -> Result<ChatSafe, WsError> {
+        match self
+            .request(WebsocketClientMessageData::CreateChat(request))
+            .await?
+        {
+            WebsocketServerResData::CreateChat(chat) => Ok(chat),
+            _ => Err(WsError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn join_chat(&self, chat_id: ObjectId) -> Result<(), WsError> {
+        match self
+            .request(WebsocketClientMessageData::JoinChat(chat_id))
+            .await?
+        {
+            WebsocketServerResData::JoinChat(_) => Ok(()),
+            _ => Err(WsError::UnexpectedResponse),
+        }
+    }
+
+    /// Also moves our read mark right away instead of waiting for the broadcast
+    pub async fn set_chat_read(&self, chat_id: ObjectId) -> Result<(), WsError> {
+        match self
+            .request(WebsocketClientMessageData::SetChatRead(chat_id))
+            .await?
+        {
+            WebsocketServerResData::SetChatRead(last_message_seen_ts) => {
+                if let Some(user_id) = CLAIMS.peek().as_ref().map(|x| x.claims.user_id) {
+                    sync::set_read_position(chat_id, user_id, last_message_seen_ts);
+                }
+
+                Ok(())
+            }
+            _ => Err(WsError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn profile_update(&self, request: UpdateRequest) -> Result<(), WsError> {
+        self.request(WebsocketClientMessageData::ProfileUpdate(request))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn media_soup(&self, message: MediaSoupMessage) -> Result<(), WsError> {
+        match self
+            .request(WebsocketClientMessageData::MS(message))
+            .await?
+        {
+            WebsocketServerResData::MS(_) => Ok(()),
+            _ => Err(WsError::UnexpectedResponse),
+        }
+    }
+}
+
+/// Delay before reconnect attempt number `attempt` (starting at 1),
+/// doubles with every attempt and is jittered down by up to a half
+fn backoff(attempt: u32) -> Duration {
+    let delay = BACKOFF_BASE
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(BACKOFF_MAX);
+
+    let mut buf = [0u8; 2];
+    let _ = getrandom::getrandom(&mut buf);
+    let jitter = u16::from_le_bytes(buf) as f64 / u16::MAX as f64;
+
+    delay.mul_f64(0.5 + jitter / 2.0)
+}
+
+fn is_browser_online() -> bool {
+    web_sys::window()
+        .map(|window| window.navigator().on_line())
+        .unwrap_or(true)
+}
+
+/// Only notifies subscribers when the state actually changes, `run` keeps
+/// setting it while waiting for a session
+fn set_state(state: ConnectionState) {
+    if *CONNECTION_STATE.peek() != state {
+        *CONNECTION_STATE.write() = state;
+    }
+}
+
+/// User the socket is allowed to act for, a session ends once this changes
+fn current_user() -> Option<ObjectId> {
+    CLAIMS.peek().as_ref().map(|x| x.claims.user_id)
+}
+
+/// Answers a command there is no socket for
+fn reject(command: WsCommand) {
+    match command {
+        WsCommand::Request(_, responder) => {
+            let _ = responder.send(Err(WsError::ConnectionLost));
+        }
+        WsCommand::ExtRequest(_, responder) => {
+            let _ = responder.send(Err(WsError::ConnectionLost));
+        }
+        // outbox waits for the connection
+        WsCommand::FlushOutbox | WsCommand::ExtNotify(_) | WsCommand::Reconnect => {}
+    }
+}
+
+/// Waits out `delay` unless a reconnect is requested, requests sent in the
+/// meantime fail right away since there is no socket to carry them
+async fn wait_reconnect(delay: Duration, ws_channel: &mut UnboundedReceiver<WsCommand>) {
+    let mut timer = TimeoutFuture::new(delay.as_millis() as u32);
+
+    loop {
+        tokio::select! {
+            _ = &mut timer => return,
+
+            command = ws_channel.next() => match command {
+                Some(WsCommand::Reconnect) => return,
+                Some(command) => reject(command),
+                None => {
+                    timer.await;
+
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn send<T: serde::Serialize>(wsio: &mut WsStream, message: &T) -> bool {
+    wsio.send(WsMessage::Text(serde_json::to_string(message).unwrap()))
+        .await
+        .is_ok()
+}
+
+pub async fn run(mut ws_channel: UnboundedReceiver<WsCommand>) {
+    let backend_url_ws = consume_context::<Config>().backend_url_ws;
+    let mut attempt = 0;
+
+    loop {
+        let user_o = CLAIMS();
+        let token = user_o.map(|x| (x.token, x.claims.user_id));
+
+        let (token, user_id) = match token {
+            Some(x) if is_browser_online() => x,
+            _ => {
+                set_state(ConnectionState::Offline);
+
+                // wait for recheck token
+                TimeoutFuture::new(1000).await;
+
+                continue;
+            }
+        };
+
+        set_state(ConnectionState::Connecting);
+
+        if let Ok((ws, mut wsio)) = WsMeta::connect(format!("{}/ws/", backend_url_ws), None).await {
+            // commands stay in the channel until the socket is authenticated
+            match handshake::authenticate(&mut wsio, &token).await {
+                Ok(()) => {
+                    attempt = 0;
+                    set_state(ConnectionState::Connected);
+
+                    session(ws, wsio, user_id, &mut ws_channel).await;
+
+                    // logged out or someone else logged in, not a dead connection
+                    if current_user() != Some(user_id) {
+                        continue;
+                    }
+                }
+                Err(WsError::Unauthorized(e)) => {
+                    warn!("websocket handshake rejected {}", e);
+                    let _ = ws.close().await;
+
+                    auth::logout();
+
+                    continue;
+                }
+                Err(e) => {
+                    warn!("websocket handshake failed {}", e);
+                    let _ = ws.close().await;
+                }
+            }
+        }
+
+        attempt += 1;
+
+        let delay = backoff(attempt);
+        set_state(ConnectionState::Reconnecting {
+            attempt,
+            next_retry_at: Utc::now() + delay,
+        });
+
+        wait_reconnect(delay, &mut ws_channel).await;
+    }
+}
+
+async fn session(
+    mut ws: WsMeta,
+    mut wsio: WsStream,
+    user_id: ObjectId,
+    ws_channel: &mut UnboundedReceiver<WsCommand>,
+) {
+    let mut message_requests: HashMap<Uuid, WsResponder> = HashMap::new();
+    let mut ext_requests: HashMap<Uuid, ExtResponder> = HashMap::new();
+
+    let mut ms_js = document::eval(include_str!("../js/mediasoup.js"));
+
+    // the connection can drop right after the handshake, `run` reconnects
+    let mut evts = match ws.observe(ObserveConfig::default()).await {
+        Ok(evts) => evts,
+        Err(e) => {
+            warn!("failed to observe websocket {}", e);
+            let _ = ws.close().await;
+
+            return;
+        }
+    };
+
+    let mut typing_sweep = TimeoutFuture::new(TYPING_SWEEP.as_millis() as u32);
+    let mut session_check = TimeoutFuture::new(SESSION_CHECK.as_millis() as u32);
+
+    // chats are fetched by `sync::resync` once connected
+    let get_self = WebsocketClientMessage {
+        id: Uuid::new_v4(),
+        data: WebsocketClientMessageData::GetSelf,
+    };
+
+    if !send(&mut wsio, &get_self).await {
+        warn!("connection lost right after the handshake");
+        let _ = ws.close().await;
+
+        return;
+    }
+
+    // replay whatever was typed while disconnected
+    outbox::flush(&mut wsio).await;
+
+    let own_presence = presence::own();
+    PRESENCE.write().insert(user_id, own_presence);
+
+    send(
+        &mut wsio,
+        &ExtClientMessage {
+            id: Uuid::new_v4(),
+            data: ExtClientMessageData::SetPresence(own_presence),
+        },
+    )
+    .await;
+
+    loop {
+        tokio::select! {
+            Some(command) = ws_channel.next() => {
+                // the socket is authenticated as the user that logged out,
+                // nothing of the next one may go over it
+                if current_user() != Some(user_id) {
+                    reject(command);
+
+                    break;
+                }
+
+                // forget requests whose caller timed out or gave up
+                message_requests.retain(|_, x| !x.is_closed());
+                ext_requests.retain(|_, x| !x.is_closed());
+
+                match command {
+                    WsCommand::Request(data, responder) => {
+                        let id = Uuid::new_v4();
+
+                        if send(&mut wsio, &WebsocketClientMessage { id, data }).await {
+                            message_requests.insert(id, responder);
+                        } else {
+                            let _ = responder.send(Err(WsError::ConnectionLost));
+                        }
+                    }
+                    WsCommand::ExtRequest(data, responder) => {
+                        let id = Uuid::new_v4();
+
+                        if send(&mut wsio, &ExtClientMessage { id, data }).await {
+                            ext_requests.insert(id, responder);
+                        } else {
+                            let _ = responder.send(Err(WsError::ConnectionLost));
+                        }
+                    }
+                    WsCommand::ExtNotify(data) => {
+                        let _ = send(&mut wsio, &ExtClientMessage { id: Uuid::new_v4(), data }).await;
+                    }
+                    // already connected
+                    WsCommand::Reconnect => {}
+                    WsCommand::FlushOutbox => outbox::flush(&mut wsio).await,
+                }
+            }
+
+            _ = &mut typing_sweep => {
+                typing::expire();
+
+                typing_sweep = TimeoutFuture::new(TYPING_SWEEP.as_millis() as u32);
+            }
+
+            _ = &mut session_check => {
+                if current_user() != Some(user_id) {
+                    break;
+                }
+
+                session_check = TimeoutFuture::new(SESSION_CHECK.as_millis() as u32);
+            }
+
+            x = evts.next() => {
+                tracing::info!("websocket event {:?}", x);
+
+                // break here connection probably dead
+                break;
+            }
+
+            Some(Text(payload)) = wsio.next() => {
+                // events meant for the user that logged out
+                if current_user() != Some(user_id) {
+                    break;
+                }
+
+                if let Ok(message) = serde_json::from_str::<ExtServerMessage>(&payload) {
+                    if ext::handle(message, &mut ext_requests, user_id) {
+                        continue;
+                    }
+                }
+
+                let message = match serde_json::from_str::<WebsocketServerMessage>(&payload) {
+                    Ok(message) => message,
+                    Err(_) => continue,
+                };
+
+                match message {
+                    WebsocketServerMessage::RequestResponse { id, data } => {
+                        outbox::resolve(id, data.as_ref().map(|_| ()).map_err(|e| e.clone()));
+
+                        match &data {
+                            Ok(WebsocketServerResData::GetChats(chats)) => {
+                                sync::merge_chats(chats.clone());
+                            }
+
+                            Ok(WebsocketServerResData::GetSelf(user)) => {
+                                USER.write().replace(user.clone());
+                            }
+
+                            Ok(WebsocketServerResData::MS(media_soup)) => {
+                                let _ = ms_js.send(WebsocketServerMessage::RequestResponse {
+                                    id,
+                                    data: Ok(WebsocketServerResData::MS(media_soup.clone()))
+                                });
+                            }
+
+                            Ok(WebsocketServerResData::NewMessage(message)) => {
+                                sync::push_message(message.clone());
+                            }
+
+                            _ => {}
+                        }
+
+                        if let Some(x) = message_requests.remove(&id) {
+                            let _ = x.send(data.map_err(WsError::Server));
+                        }
+                    }
+
+                    WebsocketServerMessage::NewMessage(message) => {
+                        if let Some(creator) = message.creator {
+                            typing::message_sent(message.chat_id, creator);
+                        }
+
+                        notifications::new_message(&message, user_id);
+
+                        // our own message can be broadcast before the request
+                        // is answered, the response replaces the local echo
+                        if let Some(message) = outbox::hold_own(message, user_id) {
+                            sync::push_message(message);
+                        }
+                    }
+
+                    WebsocketServerMessage::UserJoined { chat_id, user } => {
+                        let chats = &mut (*CHATS.write());
+                        let chat_o = chats.iter_mut().find(|x| x.id == chat_id);
+
+                        if let Some(chat) = chat_o {
+                            if !chat.users.iter().any(|x| x.id == user.id) {
+                                chat.users.push(user)
+                            }
+                        }
+
+                        storage::save_chat(chats, chat_id);
+                    }
+
+                    WebsocketServerMessage::SetChatRead { chat_id, last_message_ts } => {
+                        sync::set_read_position(chat_id, user_id, last_message_ts);
+                    }
+
+                    WebsocketServerMessage::ProfileUpdated(user) => {
+                        USER.write().replace(user);
+                    }
+
+                    WebsocketServerMessage::ProducerAdded { participant_id, producer_id } => {
+                        let _ = ms_js.send(WebsocketServerMessage::ProducerAdded { participant_id, producer_id });
+                    }
+
+                    WebsocketServerMessage::ProducerRemove { participant_id, producer_id } => {
+                        let _ = ms_js.send(WebsocketServerMessage::ProducerRemove { participant_id, producer_id });
+                    }
+                }
+            }
+
+            ms_r = ms_js.recv::<MediaSoupMessage>() => {
+                let ms = match ms_r {
+                    Ok(ms) => ms,
+                    Err(e) => {
+                        match e {
+                            EvalError::Finished => {
+                                warn!("re running document::eval");
+                                ms_js = document::eval(include_str!("../js/mediasoup.js"));
+                            }
+                            e => {
+                                info!("err {:?}", e);
+                            }
+                        }
+
+                        continue;
+                    }
+                };
+
+                let _ = wsio.send(WsMessage::Text(
+                    serde_json::to_string(&WebsocketClientMessage {
+                        id: Uuid::nil(),
+                        data: WebsocketClientMessageData::MS(ms)
+                    }).unwrap()
+                )).await;
+            }
+        }
+    }
+
+    // still open if the user changed
+    let _ = ws.close().await;
+
+    for (_, responder) in message_requests.drain() {
+        let _ = responder.send(Err(WsError::ConnectionLost));
+    }
+
+    for (_, responder) in ext_requests.drain() {
+        let _ = responder.send(Err(WsError::ConnectionLost));
+    }
+
+    // stop notifications can't reach us anymore
+    TYPING.write().clear();
+    presence::forget(user_id);
+
+    outbox::requeue();
+}
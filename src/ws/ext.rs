@@ -0,0 +1,236 @@
+//! Protocol additions the server speaks that aren't part of `shared` yet,
+//! they use the same framing as `WebsocketClientMessage` and
+//! `WebsocketServerMessage` so both can share the socket
+
+use std::collections::HashMap;
+
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{presence, sync, typing, ExtResponder, WsError};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageEdit {
+    pub chat_id: ObjectId,
+    pub message_id: ObjectId,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageDelete {
+    pub chat_id: ObjectId,
+    pub message_id: ObjectId,
+}
+
+/// `message::CreateRequest` with the message it answers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplyRequest {
+    pub chat_id: ObjectId,
+    pub content: String,
+    pub parent_id: ObjectId,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplyLink {
+    pub chat_id: ObjectId,
+    pub message_id: ObjectId,
+    pub parent_id: ObjectId,
+}
+
+/// Which of `message_ids` are replies, answered with `Vec<ReplyLink>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplyLinksRequest {
+    pub chat_id: ObjectId,
+    pub message_ids: Vec<ObjectId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangedMessage {
+    pub chat_id: ObjectId,
+    pub message_id: ObjectId,
+    pub state: sync::MessageState,
+}
+
+/// Which of `message_ids` were edited or deleted, answered with
+/// `Vec<ChangedMessage>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageStatesRequest {
+    pub chat_id: ObjectId,
+    pub message_ids: Vec<ObjectId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReactionUpdate {
+    pub chat_id: ObjectId,
+    pub message_id: ObjectId,
+    pub emoji: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reaction {
+    pub chat_id: ObjectId,
+    pub message_id: ObjectId,
+    pub user_id: ObjectId,
+    pub emoji: String,
+}
+
+/// Reactions on `message_ids`, answered with `Vec<Reaction>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReactionsRequest {
+    pub chat_id: ObjectId,
+    pub message_ids: Vec<ObjectId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypingUpdate {
+    pub chat_id: ObjectId,
+    pub typing: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypingEvent {
+    pub chat_id: ObjectId,
+    pub user_id: ObjectId,
+    pub typing: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Offline,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceEvent {
+    pub user_id: ObjectId,
+    pub status: PresenceStatus,
+}
+
+/// Current presence of `user_ids`, answered with `Vec<PresenceEvent>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceRequest {
+    pub user_ids: Vec<ObjectId>,
+}
+
+/// `SetChatRead` of another member
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadPosition {
+    pub chat_id: ObjectId,
+    pub user_id: ObjectId,
+    pub last_message_seen_ts: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "t", content = "c")]
+pub enum ExtClientMessageData {
+    EditMessage(MessageEdit),
+    DeleteMessage(MessageDelete),
+    /// answered like `NewMessage`
+    NewReply(ReplyRequest),
+    GetReplyLinks(ReplyLinksRequest),
+    GetMessageStates(MessageStatesRequest),
+    AddReaction(ReactionUpdate),
+    RemoveReaction(ReactionUpdate),
+    GetReactions(ReactionsRequest),
+    /// not answered
+    Typing(TypingUpdate),
+    /// not answered
+    SetPresence(PresenceStatus),
+    GetPresence(PresenceRequest),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtClientMessage {
+    pub id: Uuid,
+    pub data: ExtClientMessageData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "t", content = "c")]
+pub enum ExtServerMessage {
+    /// response to an `ExtClientMessage`, the payload depends on the request
+    RequestResponse {
+        id: Uuid,
+        data: Result<serde_json::Value, String>,
+    },
+    MessageEdited(MessageEdit),
+    MessageDeleted(MessageDelete),
+    /// sent along with the `NewMessage` of a reply
+    ReplyAdded(ReplyLink),
+    ReactionAdded(Reaction),
+    ReactionRemoved(Reaction),
+    Typing(TypingEvent),
+    Presence(PresenceEvent),
+    ReadPosition(ReadPosition),
+}
+
+/// Handles `message`, returns false for responses to requests that weren't
+/// ours so the `shared` protocol gets to handle them
+pub(super) fn handle(
+    message: ExtServerMessage,
+    ext_requests: &mut HashMap<Uuid, ExtResponder>,
+    user_id: ObjectId,
+) -> bool {
+    match message {
+        ExtServerMessage::RequestResponse { id, data } => match ext_requests.remove(&id) {
+            Some(responder) => {
+                let _ = responder.send(data.map_err(WsError::Server));
+
+                true
+            }
+            None => false,
+        },
+        ExtServerMessage::MessageEdited(edit) => {
+            sync::edit_message(edit);
+
+            true
+        }
+        ExtServerMessage::MessageDeleted(delete) => {
+            sync::delete_message(delete);
+
+            true
+        }
+        ExtServerMessage::ReplyAdded(link) => {
+            sync::link_replies(vec![link]);
+
+            true
+        }
+        ExtServerMessage::ReactionAdded(reaction) => {
+            sync::add_reaction(reaction);
+
+            true
+        }
+        ExtServerMessage::ReactionRemoved(reaction) => {
+            sync::remove_reaction(&reaction);
+
+            true
+        }
+        ExtServerMessage::Typing(event) => {
+            if event.user_id != user_id {
+                typing::set_typing(event);
+            }
+
+            true
+        }
+        ExtServerMessage::ReadPosition(position) => {
+            sync::set_read_position(
+                position.chat_id,
+                position.user_id,
+                position.last_message_seen_ts,
+            );
+
+            true
+        }
+        ExtServerMessage::Presence(event) => {
+            // our own status is whatever we last reported
+            if event.user_id != user_id {
+                presence::set_presence([event]);
+            }
+
+            true
+        }
+    }
+}
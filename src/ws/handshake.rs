@@ -0,0 +1,50 @@
+use futures_util::{SinkExt, StreamExt};
+use gloo_timers::future::TimeoutFuture;
+use serde::{Deserialize, Serialize};
+use ws_stream_wasm::{WsMessage, WsStream};
+
+use super::{WsError, DEFAULT_TIMEOUT};
+
+/// First frame on a new socket, keeps the token out of the url
+#[derive(Serialize)]
+#[serde(tag = "t", content = "c")]
+enum ClientHandshake<'a> {
+    Auth(&'a str),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "t", content = "c")]
+enum ServerHandshake {
+    AuthOk,
+    AuthError(String),
+}
+
+/// Authenticates the socket, nothing else may be sent before this succeeds
+pub(super) async fn authenticate(wsio: &mut WsStream, token: &str) -> Result<(), WsError> {
+    let frame = serde_json::to_string(&ClientHandshake::Auth(token)).unwrap();
+
+    wsio.send(WsMessage::Text(frame))
+        .await
+        .map_err(|_| WsError::ConnectionLost)?;
+
+    let reply = async {
+        while let Some(message) = wsio.next().await {
+            if let WsMessage::Text(payload) = message {
+                if let Ok(reply) = serde_json::from_str::<ServerHandshake>(&payload) {
+                    return Some(reply);
+                }
+            }
+        }
+
+        None
+    };
+
+    tokio::select! {
+        reply = reply => match reply {
+            Some(ServerHandshake::AuthOk) => Ok(()),
+            Some(ServerHandshake::AuthError(e)) => Err(WsError::Unauthorized(e)),
+            None => Err(WsError::ConnectionLost),
+        },
+        _ = TimeoutFuture::new(DEFAULT_TIMEOUT.as_millis() as u32) => Err(WsError::Timeout),
+    }
+}
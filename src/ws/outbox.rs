@@ -0,0 +1,344 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use bson::oid::ObjectId;
+use chrono::Utc;
+use dioxus::prelude::*;
+use gloo_timers::future::TimeoutFuture;
+use shared::api::{
+    message::CreateRequest,
+    websocket::{WebsocketClientMessage, WebsocketClientMessageData},
+};
+use shared::models::message::Message;
+use uuid::Uuid;
+use ws_stream_wasm::WsStream;
+
+use super::{
+    ext::{ExtClientMessage, ExtClientMessageData, ReplyRequest},
+    send, sync, WsError, DEFAULT_TIMEOUT,
+};
+use crate::{CHATS, CLAIMS, OUTBOX};
+
+thread_local! {
+    /// Our own messages broadcast while a send to their chat waits for its
+    /// response, they can't be told apart from the local echoes until then
+    static HELD: RefCell<Vec<Message>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutboxStatus {
+    /// waiting for a connection
+    Queued,
+    /// sent, waiting for the server to store it
+    Sending,
+    /// sent on a connection that dropped before the response, waits for the
+    /// resync to tell whether the server stored it
+    Unconfirmed,
+    Failed(String),
+}
+
+/// Message typed by the user that the server hasn't confirmed yet,
+/// `id` doubles as the websocket request id so responses map back to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutgoingMessage {
+    pub id: Uuid,
+    /// temporary id of the local echo in `CHATS`
+    pub message_id: ObjectId,
+    pub chat_id: ObjectId,
+    pub content: String,
+    /// message this one replies to
+    pub parent_id: Option<ObjectId>,
+    pub status: OutboxStatus,
+    /// times it was sent, tells a stale timeout from the current attempt
+    attempts: u32,
+}
+
+/// Queues the message and shows it in its chat right away
+pub fn enqueue(chat_id: ObjectId, content: String, parent_id: Option<ObjectId>) -> Uuid {
+    let id = Uuid::new_v4();
+    let message_id = ObjectId::new();
+
+    OUTBOX.write().push(OutgoingMessage {
+        id,
+        message_id,
+        chat_id,
+        content: content.clone(),
+        parent_id,
+        status: OutboxStatus::Queued,
+        attempts: 0,
+    });
+
+    sync::add_echo(Message {
+        id: message_id,
+        chat_id,
+        creator: CLAIMS.peek().as_ref().map(|x| x.claims.user_id),
+        content,
+        created_at: Utc::now(),
+    });
+
+    id
+}
+
+/// Whether `message_id` is a local echo rather than a stored message
+pub fn is_echo(message_id: ObjectId) -> bool {
+    OUTBOX.peek().iter().any(|x| x.message_id == message_id)
+}
+
+pub fn retry(id: Uuid) {
+    if let Some(message) = OUTBOX.write().iter_mut().find(|x| x.id == id) {
+        message.status = OutboxStatus::Queued;
+    }
+}
+
+pub fn discard(id: Uuid) {
+    let discarded = {
+        let outbox = &mut (*OUTBOX.write());
+
+        outbox
+            .iter()
+            .position(|x| x.id == id)
+            .map(|i| outbox.remove(i))
+    };
+
+    if let Some(message) = discarded {
+        sync::remove_echo(message.chat_id, message.message_id);
+    }
+}
+
+/// Drops everything of the session, nothing of it may be sent later
+pub fn clear() {
+    OUTBOX.write().clear();
+    HELD.with_borrow_mut(|x| x.clear());
+}
+
+/// Sends queued messages in the order they were typed, stops at the first
+/// failed send and leaves the rest queued for the next connection
+pub(super) async fn flush(wsio: &mut WsStream) {
+    let queued = OUTBOX
+        .read()
+        .iter()
+        .filter(|x| x.status == OutboxStatus::Queued)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for message in queued {
+        let sent = match message.parent_id {
+            Some(parent_id) => {
+                let request = ExtClientMessage {
+                    id: message.id,
+                    data: ExtClientMessageData::NewReply(ReplyRequest {
+                        chat_id: message.chat_id,
+                        content: message.content,
+                        parent_id,
+                    }),
+                };
+
+                send(wsio, &request).await
+            }
+            None => {
+                let request = WebsocketClientMessage {
+                    id: message.id,
+                    data: WebsocketClientMessageData::NewMessage(CreateRequest {
+                        chat_id: message.chat_id,
+                        content: message.content,
+                    }),
+                };
+
+                send(wsio, &request).await
+            }
+        };
+
+        if !sent {
+            return;
+        }
+
+        let attempt = message.attempts + 1;
+
+        if let Some(x) = OUTBOX.write().iter_mut().find(|x| x.id == message.id) {
+            x.status = OutboxStatus::Sending;
+            x.attempts = attempt;
+        }
+
+        spawn(expire(message.id, attempt));
+    }
+}
+
+/// Fails the message if attempt `attempt` isn't answered in time, like
+/// `WsClient::response` does for other requests
+async fn expire(id: Uuid, attempt: u32) {
+    TimeoutFuture::new(DEFAULT_TIMEOUT.as_millis() as u32).await;
+
+    let waiting = OUTBOX
+        .peek()
+        .iter()
+        .any(|x| x.id == id && x.attempts == attempt && x.status == OutboxStatus::Sending);
+
+    if waiting {
+        resolve(id, Err(WsError::Timeout.to_string()));
+    }
+}
+
+/// Resolves the outbox entry for request `id`, returns false if the request
+/// didn't come from the outbox. On success the local echo is dropped, the
+/// caller adds the stored message from the response in its place
+pub(super) fn resolve(id: Uuid, result: Result<(), String>) -> bool {
+    // most responses aren't for the outbox, don't touch it for them
+    let index = match OUTBOX.peek().iter().position(|x| x.id == id) {
+        Some(index) => index,
+        None => return false,
+    };
+
+    let resolved = {
+        let outbox = &mut (*OUTBOX.write());
+
+        match result {
+            Ok(_) => Ok(outbox.remove(index)),
+            Err(e) => {
+                outbox[index].status = OutboxStatus::Failed(e);
+
+                Err(outbox[index].chat_id)
+            }
+        }
+    };
+
+    let chat_id = match resolved {
+        Ok(message) => {
+            sync::remove_echo(message.chat_id, message.message_id);
+
+            message.chat_id
+        }
+        Err(chat_id) => {
+            // undo `sync::bump_chat`
+            sync::sort_chats(&mut CHATS.write());
+
+            chat_id
+        }
+    };
+
+    release(chat_id);
+
+    true
+}
+
+fn sending_to(chat_id: ObjectId) -> bool {
+    OUTBOX
+        .peek()
+        .iter()
+        .any(|x| x.chat_id == chat_id && x.status == OutboxStatus::Sending)
+}
+
+/// Our own broadcast `message` is held back while a send to its chat waits
+/// for the response that replaces the echo, returns it if it can be shown
+pub(super) fn hold_own(message: Message, user_id: ObjectId) -> Option<Message> {
+    if message.creator != Some(user_id) || !sending_to(message.chat_id) {
+        return Some(message);
+    }
+
+    HELD.with_borrow_mut(|x| x.push(message));
+
+    None
+}
+
+/// Shows held messages of `chat_id` once nothing is waiting there anymore
+fn release(chat_id: ObjectId) {
+    if sending_to(chat_id) {
+        return;
+    }
+
+    let held = HELD.with_borrow_mut(|held| {
+        let (released, rest) = std::mem::take(held)
+            .into_iter()
+            .partition::<Vec<_>, _>(|x| x.chat_id == chat_id);
+
+        *held = rest;
+
+        released
+    });
+
+    for message in held {
+        sync::push_message(message);
+    }
+}
+
+/// Messages sent on a dead connection wait for the resync, unless our own
+/// broadcast of them already came in and shows they were stored
+pub(super) fn requeue() {
+    // held messages are all ours
+    for message in HELD.take() {
+        let delivered = OUTBOX
+            .peek()
+            .iter()
+            .find(|x| {
+                x.chat_id == message.chat_id
+                    && x.status == OutboxStatus::Sending
+                    && x.content == message.content
+            })
+            .map(|x| x.id);
+
+        if let Some(id) = delivered {
+            resolve(id, Ok(()));
+        }
+
+        sync::push_message(message);
+    }
+
+    for message in OUTBOX.write().iter_mut() {
+        if message.status == OutboxStatus::Sending {
+            message.status = OutboxStatus::Unconfirmed;
+        }
+    }
+}
+
+/// Resolves unconfirmed messages to `chat_id` that are among `missed`, the
+/// messages stored while disconnected, each stored message confirms one
+pub(super) fn confirm(chat_id: ObjectId, missed: &[Message]) {
+    let user_id = match CLAIMS.peek().as_ref() {
+        Some(auth) => auth.claims.user_id,
+        None => return,
+    };
+
+    let mut used = HashSet::new();
+
+    let delivered = OUTBOX
+        .peek()
+        .iter()
+        .filter(|x| x.chat_id == chat_id && x.status == OutboxStatus::Unconfirmed)
+        .filter_map(|entry| {
+            let stored = missed.iter().find(|x| {
+                !used.contains(&x.id) && x.creator == Some(user_id) && x.content == entry.content
+            })?;
+
+            used.insert(stored.id);
+
+            Some(entry.id)
+        })
+        .collect::<Vec<_>>();
+
+    for id in delivered {
+        resolve(id, Ok(()));
+    }
+}
+
+pub(super) fn unconfirmed_in(chat_id: ObjectId) -> bool {
+    OUTBOX
+        .peek()
+        .iter()
+        .any(|x| x.chat_id == chat_id && x.status == OutboxStatus::Unconfirmed)
+}
+
+/// Unconfirmed messages to the `checked` chats that the resync didn't find
+/// are sent again
+pub(super) fn requeue_unconfirmed(checked: &HashSet<ObjectId>) {
+    let unconfirmed =
+        |x: &OutgoingMessage| x.status == OutboxStatus::Unconfirmed && checked.contains(&x.chat_id);
+
+    if !OUTBOX.peek().iter().any(unconfirmed) {
+        return;
+    }
+
+    for message in OUTBOX.write().iter_mut() {
+        if unconfirmed(message) {
+            message.status = OutboxStatus::Queued;
+        }
+    }
+}
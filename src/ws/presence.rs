@@ -0,0 +1,84 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use bson::oid::ObjectId;
+use dioxus::prelude::*;
+use dioxus_logger::tracing::warn;
+
+use super::{
+    ext::{ExtClientMessageData, PresenceEvent, PresenceStatus},
+    WsClient,
+};
+use crate::{CHATS, CLAIMS, PRESENCE};
+
+/// No input for this long counts as away
+const IDLE_AFTER: Duration = Duration::from_secs(5 * 60);
+
+thread_local! {
+    /// What we last reported, repeated on every new session
+    static OWN: Cell<PresenceStatus> = const { Cell::new(PresenceStatus::Online) };
+}
+
+pub(super) fn own() -> PresenceStatus {
+    OWN.get()
+}
+
+pub(super) fn set_presence(events: impl IntoIterator<Item = PresenceEvent>) {
+    let mut presence = PRESENCE.write();
+
+    for event in events {
+        presence.insert(event.user_id, event.status);
+    }
+}
+
+/// Nobody tells us about others while disconnected
+pub(super) fn forget(user_id: ObjectId) {
+    PRESENCE.write().retain(|x, _| *x == user_id);
+}
+
+/// Presence of everyone we share a chat with
+pub(super) async fn refresh(ws: WsClient) {
+    let mut user_ids = CHATS
+        .peek()
+        .iter()
+        .flat_map(|x| x.users.iter().map(|x| x.id))
+        .collect::<Vec<_>>();
+
+    user_ids.sort();
+    user_ids.dedup();
+
+    if user_ids.is_empty() {
+        return;
+    }
+
+    match ws.get_presence(user_ids).await {
+        Ok(events) => set_presence(events),
+        Err(e) => warn!("failed to get presence {}", e),
+    }
+}
+
+/// Follows page visibility and input activity, reporting changes
+pub async fn watch(ws: WsClient) {
+    let mut eval = document::eval(include_str!("../../js/presence.js"));
+
+    let _ = eval.send(IDLE_AFTER.as_millis() as u64);
+
+    loop {
+        let status = match eval.recv::<PresenceStatus>().await {
+            Ok(status) => status,
+            Err(e) => {
+                warn!("presence script stopped {}", e);
+
+                return;
+            }
+        };
+
+        OWN.set(status);
+
+        if let Some(user_id) = CLAIMS.peek().as_ref().map(|x| x.claims.user_id) {
+            PRESENCE.write().insert(user_id, status);
+        }
+
+        ws.ext_notify(ExtClientMessageData::SetPresence(status));
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::HashSet;
+
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use dioxus::prelude::*;
+use dioxus_logger::tracing::warn;
+use serde::{Deserialize, Serialize};
+use shared::api::message::GetRequest;
+use shared::models::{chat::ChatSafe, message::Message};
+
+use super::{
+    ext::{ChangedMessage, MessageDelete, MessageEdit, Reaction, ReplyLink},
+    outbox, presence, WsClient, WsError,
+};
+use crate::{storage, CHATS, MESSAGE_STATES, REACTIONS, REPLIES};
+
+/// Changes to a message after it was sent, only known for changes seen by
+/// this client, cached along with the messages
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MessageState {
+    Edited,
+    Deleted,
+}
+
+/// Pages fetched per chat when filling the gap left by a disconnect, if the
+/// gap is bigger the old history is dropped instead of keeping a hole in it
+const MAX_GAP_PAGES: usize = 10;
+
+pub(super) fn sort_chats(chats: &mut [ChatSafe]) {
+    chats.sort_by(|a, b| a.last_message_ts.cmp(&b.last_message_ts).reverse());
+}
+
+/// Adds `new` to `messages` skipping ids that are already there,
+/// keeps `messages` ordered oldest first
+fn insert_messages(messages: &mut Vec<Message>, new: Vec<Message>) {
+    let known = messages.iter().map(|x| x.id).collect::<HashSet<_>>();
+
+    messages.extend(new.into_iter().filter(|x| !known.contains(&x.id)));
+    messages.sort_by_key(|x| x.created_at);
+}
+
+/// Moves the chat to the top of the list before the server confirms a new
+/// message, `sort_chats` restores the order if the message fails
+pub(super) fn bump_chat(chat_id: ObjectId) {
+    let chats = &mut (*CHATS.write());
+
+    if let Some(index) = chats.iter().position(|x| x.id == chat_id) {
+        let chat = chats.remove(index);
+
+        chats.insert(0, chat);
+    }
+}
+
+/// Shows a message that is still being sent, not cached
+pub(super) fn add_echo(message: Message) {
+    if let Some(chat) = CHATS.write().iter_mut().find(|x| x.id == message.chat_id) {
+        insert_messages(&mut chat.messages, vec![message]);
+    }
+}
+
+pub(super) fn remove_echo(chat_id: ObjectId, message_id: ObjectId) {
+    if let Some(chat) = CHATS.write().iter_mut().find(|x| x.id == chat_id) {
+        chat.messages.retain(|x| x.id != message_id);
+    }
+}
+
+pub fn merge_messages(chat_id: ObjectId, messages: Vec<Message>) {
+    let chats = &mut (*CHATS.write());
+
+    if let Some(chat) = chats.iter_mut().find(|x| x.id == chat_id) {
+        insert_messages(&mut chat.messages, messages);
+    }
+
+    storage::save_chat(chats, chat_id);
+}
+
+pub(super) fn push_message(message: Message) {
+    let chats = &mut (*CHATS.write());
+    let chat_o = chats.iter_mut().find(|x| x.id == message.chat_id);
+
+    let chat_id = message.chat_id;
+
+    if let Some(chat) = chat_o {
+        if message.created_at > chat.last_message_ts {
+            chat.last_message_ts = message.created_at;
+        }
+
+        insert_messages(&mut chat.messages, vec![message]);
+    }
+
+    sort_chats(chats);
+    storage::save_chat(chats, chat_id);
+}
+
+fn find_message(
+    chats: &mut [ChatSafe],
+    chat_id: ObjectId,
+    message_id: ObjectId,
+) -> Option<&mut Message> {
+    chats
+        .iter_mut()
+        .find(|x| x.id == chat_id)?
+        .messages
+        .iter_mut()
+        .find(|x| x.id == message_id)
+}
+
+pub(super) fn edit_message(edit: MessageEdit) {
+    // a late edit doesn't bring a deleted message back
+    if MESSAGE_STATES.peek().get(&edit.message_id) == Some(&MessageState::Deleted) {
+        return;
+    }
+
+    // set first, the cache stores it with the chat
+    MESSAGE_STATES
+        .write()
+        .insert(edit.message_id, MessageState::Edited);
+
+    let chats = &mut (*CHATS.write());
+
+    if let Some(message) = find_message(chats, edit.chat_id, edit.message_id) {
+        message.content = edit.content;
+    }
+
+    storage::save_chat(chats, edit.chat_id);
+}
+
+/// Deleted messages keep their place with empty content
+pub(super) fn delete_message(delete: MessageDelete) {
+    MESSAGE_STATES
+        .write()
+        .insert(delete.message_id, MessageState::Deleted);
+
+    let chats = &mut (*CHATS.write());
+
+    if let Some(message) = find_message(chats, delete.chat_id, delete.message_id) {
+        message.content.clear();
+    }
+
+    storage::save_chat(chats, delete.chat_id);
+}
+
+/// Adds edits and deletes the server knows of, a delete seen live stays
+pub(super) fn set_message_states(chat_id: ObjectId, changed: Vec<ChangedMessage>) {
+    if changed.is_empty() {
+        return;
+    }
+
+    let deleted = {
+        let states = &mut (*MESSAGE_STATES.write());
+
+        changed
+            .into_iter()
+            .filter(|x| states.get(&x.message_id) != Some(&MessageState::Deleted))
+            .filter_map(|x| {
+                states.insert(x.message_id, x.state);
+
+                (x.state == MessageState::Deleted).then_some(x.message_id)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let chats = &mut (*CHATS.write());
+
+    // like `delete_message`, deleted messages keep their place empty
+    for message_id in deleted {
+        if let Some(message) = find_message(chats, chat_id, message_id) {
+            message.content.clear();
+        }
+    }
+
+    storage::save_chat(chats, chat_id);
+}
+
+pub(super) fn link_replies(links: Vec<ReplyLink>) {
+    if links.is_empty() {
+        return;
+    }
+
+    let replies = &mut (*REPLIES.write());
+
+    for link in links {
+        replies.insert(link.message_id, link.parent_id);
+    }
+}
+
+pub(super) fn add_reaction(reaction: Reaction) {
+    let reactions = &mut (*REACTIONS.write());
+    let message = reactions.entry(reaction.message_id).or_default();
+
+    if !message.contains(&reaction) {
+        message.push(reaction);
+    }
+}
+
+pub(super) fn remove_reaction(reaction: &Reaction) {
+    if let Some(message) = REACTIONS.write().get_mut(&reaction.message_id) {
+        message.retain(|x| x != reaction);
+    }
+}
+
+/// Replaces what is known about the reactions on `message_ids`
+pub(super) fn set_reactions(message_ids: &[ObjectId], fresh: Vec<Reaction>) {
+    let reactions = &mut (*REACTIONS.write());
+
+    for message_id in message_ids {
+        reactions.remove(message_id);
+    }
+
+    for reaction in fresh {
+        reactions
+            .entry(reaction.message_id)
+            .or_default()
+            .push(reaction);
+    }
+}
+
+/// Pages back through the history of `chat_id` until `message_id` is
+/// loaded, returns false if it wasn't found
+pub async fn load_message(
+    ws: WsClient,
+    chat_id: ObjectId,
+    message_id: ObjectId,
+) -> Result<bool, WsError> {
+    for _ in 0..MAX_GAP_PAGES {
+        let oldest = {
+            let chats = CHATS.read();
+
+            let chat = match chats.iter().find(|x| x.id == chat_id) {
+                Some(chat) => chat,
+                None => return Ok(false),
+            };
+
+            if chat.messages.iter().any(|x| x.id == message_id) {
+                return Ok(true);
+            }
+
+            chat.messages.first().map(|x| x.created_at)
+        };
+
+        let page = ws
+            .get_messages(GetRequest {
+                chat_id,
+                last_message_ts: oldest,
+            })
+            .await?;
+
+        if page.is_empty() {
+            return Ok(false);
+        }
+
+        merge_messages(chat_id, page);
+    }
+
+    Ok(false)
+}
+
+/// Replaces the chat list keeping history that was already loaded
+pub(super) fn merge_chats(fresh: Vec<ChatSafe>) {
+    let chats = &mut (*CHATS.write());
+
+    let mut merged = fresh
+        .into_iter()
+        .map(|mut chat| {
+            if let Some(old) = chats.iter_mut().find(|x| x.id == chat.id) {
+                let messages = std::mem::take(&mut chat.messages);
+
+                chat.messages = std::mem::take(&mut old.messages);
+                insert_messages(&mut chat.messages, messages);
+            }
+
+            chat
+        })
+        .collect::<Vec<_>>();
+
+    sort_chats(&mut merged);
+    storage::save_chats(&merged);
+
+    *chats = merged;
+}
+
+pub(super) fn set_read_position(
+    chat_id: ObjectId,
+    user_id: ObjectId,
+    last_message_seen_ts: DateTime<Utc>,
+) {
+    let chats = &mut (*CHATS.write());
+
+    if let Some(chat_user) = chats
+        .iter_mut()
+        .find(|x| x.id == chat_id)
+        .and_then(|chat| chat.users.iter_mut().find(|x| x.id == user_id))
+    {
+        chat_user.last_message_seen_ts = last_message_seen_ts;
+    }
+
+    storage::save_chat(chats, chat_id);
+}
+
+/// Refreshes the chat list and presence and fetches messages missed while
+/// disconnected, then sends again what the missed messages don't show as
+/// delivered. Chats that couldn't be checked keep their unconfirmed messages
+/// for the next resync
+pub async fn resync(ws: WsClient) {
+    let checked = catch_up(ws).await;
+
+    outbox::requeue_unconfirmed(&checked);
+    ws.flush_outbox();
+}
+
+/// Returns the chats whose missed messages were fetched, or that had none
+async fn catch_up(ws: WsClient) -> HashSet<ObjectId> {
+    let mut checked = HashSet::new();
+
+    // newest loaded message per chat, taken before the fresh list comes in.
+    // Chats without one are only checked if something sent there is
+    // unconfirmed, from the last message the list knew of
+    let known = CHATS
+        .read()
+        .iter()
+        .filter_map(|chat| {
+            let newest = chat
+                .messages
+                .iter()
+                .rev()
+                .find(|x| !outbox::is_echo(x.id))
+                .map(|x| x.created_at);
+
+            match newest {
+                Some(newest) => Some((chat.id, newest)),
+                None if outbox::unconfirmed_in(chat.id) => Some((chat.id, chat.last_message_ts)),
+                None => None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if let Err(e) = ws.get_chats().await {
+        warn!("failed to resync chats {}", e);
+
+        return checked;
+    }
+
+    presence::refresh(ws).await;
+
+    for (chat_id, newest) in known {
+        let behind = CHATS
+            .read()
+            .iter()
+            .any(|x| x.id == chat_id && x.last_message_ts > newest);
+
+        if !behind {
+            checked.insert(chat_id);
+
+            continue;
+        }
+
+        let mut gap = Vec::new();
+        let mut last_message_ts = None;
+        let mut closed = false;
+
+        for _ in 0..MAX_GAP_PAGES {
+            let page = match ws
+                .get_messages(GetRequest {
+                    chat_id,
+                    last_message_ts,
+                })
+                .await
+            {
+                Ok(page) => page,
+                Err(e) => {
+                    warn!("failed to fetch missed messages {}", e);
+
+                    return checked;
+                }
+            };
+
+            let oldest = page.first().map(|x| x.created_at);
+
+            gap.extend(page);
+
+            match oldest {
+                Some(ts) if ts > newest => last_message_ts = Some(ts),
+                _ => {
+                    closed = true;
+
+                    break;
+                }
+            }
+        }
+
+        outbox::confirm(chat_id, &gap);
+        checked.insert(chat_id);
+
+        if closed {
+            merge_messages(chat_id, gap);
+        } else {
+            let chats = &mut (*CHATS.write());
+
+            if let Some(chat) = chats.iter_mut().find(|x| x.id == chat_id) {
+                // messages still being sent stay
+                chat.messages.retain(|x| outbox::is_echo(x.id));
+                insert_messages(&mut chat.messages, gap);
+            }
+
+            storage::save_chat(chats, chat_id);
+        }
+    }
+
+    checked
+}
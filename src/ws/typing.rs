@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use bson::oid::ObjectId;
+use chrono::Utc;
+use dioxus::prelude::*;
+use gloo_timers::future::TimeoutFuture;
+
+use super::{
+    ext::{ExtClientMessageData, TypingEvent, TypingUpdate},
+    WsClient,
+};
+use crate::TYPING;
+
+/// A started notification is repeated this often while the user keeps typing
+const RESEND_AFTER: Duration = Duration::from_secs(3);
+/// Typing stops after this long without input
+const IDLE_AFTER: Duration = Duration::from_secs(4);
+/// Others count as typing this long after their last notification, longer
+/// than `RESEND_AFTER` so a steady typist doesn't flicker
+pub(super) const EXPIRE_AFTER: Duration = Duration::from_secs(6);
+
+pub(super) fn set_typing(event: TypingEvent) {
+    let key = (event.chat_id, event.user_id);
+
+    match event.typing {
+        true => {
+            TYPING.write().insert(key, Utc::now() + EXPIRE_AFTER);
+        }
+        false => {
+            TYPING.write().remove(&key);
+        }
+    }
+}
+
+/// Their message arrived, so they are done typing it
+pub(super) fn message_sent(chat_id: ObjectId, user_id: ObjectId) {
+    if TYPING.peek().contains_key(&(chat_id, user_id)) {
+        TYPING.write().remove(&(chat_id, user_id));
+    }
+}
+
+pub(super) fn expire() {
+    let now = Utc::now();
+
+    if TYPING.peek().values().any(|x| *x <= now) {
+        TYPING.write().retain(|_, x| *x > now);
+    }
+}
+
+/// Throttles the typing notifications of one message input
+#[derive(Clone, Copy)]
+pub struct TypingNotifier {
+    ws: WsClient,
+    /// chat we told the server we're typing in and when
+    sent_signal: Signal<Option<(ObjectId, chrono::DateTime<Utc>)>>,
+    idle_task_signal: Signal<Option<Task>>,
+}
+
+pub fn use_typing_notifier() -> TypingNotifier {
+    TypingNotifier {
+        ws: super::use_ws(),
+        sent_signal: use_signal(|| None),
+        idle_task_signal: use_signal(|| None),
+    }
+}
+
+impl TypingNotifier {
+    fn notify(&self, chat_id: ObjectId, typing: bool) {
+        self.ws
+            .ext_notify(ExtClientMessageData::Typing(TypingUpdate {
+                chat_id,
+                typing,
+            }));
+    }
+
+    /// Call on every change of the input
+    pub fn typed(&mut self, chat_id: ObjectId) {
+        let now = Utc::now();
+        let sent = *self.sent_signal.peek();
+
+        match sent {
+            Some((sent_chat_id, at)) if sent_chat_id == chat_id && now < at + RESEND_AFTER => {}
+            _ => {
+                if let Some((sent_chat_id, _)) = sent.filter(|(x, _)| *x != chat_id) {
+                    self.notify(sent_chat_id, false);
+                }
+
+                self.notify(chat_id, true);
+                self.sent_signal.set(Some((chat_id, now)));
+            }
+        }
+
+        if let Some(task) = self.idle_task_signal.take() {
+            task.cancel();
+        }
+
+        let mut notifier = *self;
+
+        self.idle_task_signal.set(Some(spawn(async move {
+            TimeoutFuture::new(IDLE_AFTER.as_millis() as u32).await;
+
+            notifier.idle_task_signal.set(None);
+            notifier.stop();
+        })));
+    }
+
+    /// Call when the message is sent or the input is left for another chat
+    pub fn stop(&mut self) {
+        if let Some(task) = self.idle_task_signal.take() {
+            task.cancel();
+        }
+
+        if let Some((chat_id, _)) = self.sent_signal.take() {
+            self.notify(chat_id, false);
+        }
+    }
+}